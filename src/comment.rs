@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommentKind {
+    /// `// ...` up to the end of the line.
+    Line,
+    /// `/* ... */`, possibly spanning several lines.
+    Block,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// Byte range of the comment, delimiters included.
+    pub span: Range<usize>,
+    /// The comment's text, delimiters included.
+    pub text: String,
+}
+
+/// Source with every comment blanked out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrippedSource {
+    /// The source with each comment byte replaced by a space, except
+    /// newlines, so every offset and line number is unchanged.
+    pub text: String,
+    /// The removed comments, in order.
+    pub comments: Vec<Comment>,
+}
+
+/// The kind of comment `sfz_source` starts with, if any.
+pub fn comment_start(sfz_source: &str) -> Option<CommentKind> {
+    if sfz_source.starts_with("//") {
+        Some(CommentKind::Line)
+    } else if sfz_source.starts_with("/*") {
+        Some(CommentKind::Block)
+    } else {
+        None
+    }
+}
+
+/// Finds where the next comment starts, if any.
+pub fn find_comment(sfz_source: &str) -> Option<(usize, CommentKind)> {
+    let mut from = 0;
+    while let Some(found) = sfz_source[from..].find('/') {
+        let start = from + found;
+        match comment_start(&sfz_source[start..]) {
+            Some(kind) => return Some((start, kind)),
+            None => from = start + 1,
+        }
+    }
+    None
+}
+
+/// The length of the comment at the start of `sfz_source`. A block
+/// comment which is never closed runs to the end of the source.
+pub fn comment_len(sfz_source: &str, kind: CommentKind) -> usize {
+    match kind {
+        CommentKind::Line => sfz_source.find(['\r', '\n']).unwrap_or(sfz_source.len()),
+        CommentKind::Block => sfz_source[2..]
+            .find("*/")
+            .map_or(sfz_source.len(), |end| 2 + end + 2),
+    }
+}
+
+/// Replaces `//` line comments and `/* */` block comments with spaces.
+pub fn strip_comments(sfz_source: &str) -> StrippedSource {
+    let mut text = String::with_capacity(sfz_source.len());
+    let mut comments = vec![];
+    let mut copied = 0;
+
+    while let Some((found, kind)) = find_comment(&sfz_source[copied..]) {
+        let start = copied + found;
+        let end = start + comment_len(&sfz_source[start..], kind);
+        text.push_str(&sfz_source[copied..start]);
+        for c in sfz_source[start..end].chars() {
+            match c {
+                '\n' | '\r' => text.push(c),
+                _ => text.extend(std::iter::repeat_n(' ', c.len_utf8())),
+            }
+        }
+        comments.push(Comment { kind, span: start..end, text: sfz_source[start..end].to_owned() });
+        copied = end;
+    }
+    text.push_str(&sfz_source[copied..]);
+
+    StrippedSource { text, comments }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_offsets_of_multi_byte_characters() {
+        let source = "<region> // größe ♪\nsample=é.wav /* ü */ key=60";
+        let stripped = strip_comments(source);
+        assert_eq!(stripped.text.len(), source.len());
+        assert_eq!(stripped.text, format!("<region> {}\nsample=é.wav {} key=60", " ".repeat(14), " ".repeat(8)));
+        assert_eq!(stripped.text.find("key=60"), source.find("key=60"));
+        let texts: Vec<_> = stripped.comments.iter().map(|comment| &source[comment.span.clone()]).collect();
+        assert_eq!(texts, ["// größe ♪", "/* ü */"]);
+    }
+
+    #[test]
+    fn runs_an_unterminated_block_comment_to_the_end() {
+        let source = "<region> key=60 /* lokey=1\nhikey=2 *";
+        let stripped = strip_comments(source);
+        assert_eq!(stripped.text, format!("<region> key=60 {}\n{}", " ".repeat(10), " ".repeat(9)));
+        assert_eq!(stripped.comments.len(), 1);
+        assert_eq!(stripped.comments[0].kind, CommentKind::Block);
+        assert_eq!(stripped.comments[0].span, 16..source.len());
+    }
+
+    #[test]
+    fn stops_line_comments_at_crlf() {
+        let source = "<region> // a\r\nkey=60 /* b\r\nc */ lokey=1\r\n";
+        let stripped = strip_comments(source);
+        assert_eq!(stripped.text, "<region>     \r\nkey=60     \r\n     lokey=1\r\n");
+        assert_eq!(stripped.comments[0].text, "// a");
+        assert_eq!(stripped.comments[1].text, "/* b\r\nc */");
+    }
+
+    #[test]
+    fn leaves_single_slashes_alone() {
+        assert_eq!(find_comment("sample=a/b.wav // c"), Some((15, CommentKind::Line)));
+        assert_eq!(strip_comments("sample=a/b.wav").comments, []);
+    }
+}
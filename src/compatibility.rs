@@ -0,0 +1,145 @@
+use crate::error::{SfzError, SfzErrorKind};
+use crate::instrument::{load_sfz_tolerant_with, parse_sfz_tolerant_with};
+use crate::preprocessor::{FileLoader, Preprocessor};
+use crate::profile::PlayerProfile;
+use std::path::Path;
+
+/// What one player would ignore when loading an instrument.
+#[derive(Clone, Debug)]
+pub struct CompatibilityReport {
+    pub player: PlayerProfile,
+    /// Warnings for each unsupported opcode, header and directive, in
+    /// document order. Opcodes in an unsupported header aren't listed.
+    pub issues: Vec<SfzError>,
+}
+
+impl CompatibilityReport {
+    /// Whether the player reads everything the instrument uses.
+    pub fn is_compatible(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn opcodes(&self) -> impl Iterator<Item = &SfzError> {
+        self.of_kind(SfzErrorKind::UnsupportedOpcode)
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = &SfzError> {
+        self.of_kind(SfzErrorKind::UnsupportedHeader)
+    }
+
+    pub fn directives(&self) -> impl Iterator<Item = &SfzError> {
+        self.of_kind(SfzErrorKind::UnsupportedDirective)
+    }
+
+    fn of_kind(&self, kind: SfzErrorKind) -> impl Iterator<Item = &SfzError> {
+        self.issues.iter().filter(move |issue| issue.kind == kind)
+    }
+}
+
+fn is_compatibility_issue(diagnostic: &SfzError) -> bool {
+    matches!(
+        diagnostic.kind,
+        SfzErrorKind::UnsupportedOpcode | SfzErrorKind::UnsupportedHeader | SfzErrorKind::UnsupportedDirective
+    )
+}
+
+/// Checks a document against each of `players`. Directives are checked as
+/// written, so `sfz_source` shouldn't have been preprocessed.
+pub fn check_compatibility(sfz_source: &str, players: &[PlayerProfile]) -> Vec<CompatibilityReport> {
+    players
+        .iter()
+        .map(|player| {
+            let outcome = parse_sfz_tolerant_with(sfz_source, &player.parse_options());
+            let issues = outcome.diagnostics.into_iter().filter(is_compatibility_issue).collect();
+            CompatibilityReport { player: *player, issues }
+        })
+        .collect()
+}
+
+/// Loads the file at `path` and checks it against each of `players`, with
+/// issues pointing into the file they were found in, includes and all.
+pub fn load_compatibility<L: FileLoader>(
+    preprocessor: &Preprocessor<L>,
+    path: impl AsRef<Path>,
+    players: &[PlayerProfile],
+) -> Result<Vec<CompatibilityReport>, SfzError> {
+    players
+        .iter()
+        .map(|player| {
+            let (outcome, _) = load_sfz_tolerant_with(preprocessor, path.as_ref(), &player.parse_options())?;
+            let issues = outcome.diagnostics.into_iter().filter(is_compatibility_issue).collect();
+            Ok(CompatibilityReport { player: *player, issues })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessor::MemoryLoader;
+    use crate::profile::ALL_PLAYERS;
+
+    const SFZ: &str = "#define $X 1\n\
+                       <control> default_path=a/ set_cc7=100\n\
+                       <master> sw_label=x\n\
+                       <group> polyphony_stealing=2\n\
+                       <region> sample=a.wav off_mode=fast\n\
+                       <effect> type=lofi\n\
+                       <midi>\n\
+                       <sample> name=x\n";
+
+    fn lines<'a>(issues: impl Iterator<Item = &'a SfzError>) -> Vec<(usize, Option<&'a str>)> {
+        issues.map(|issue| (issue.line, issue.opcode.as_deref())).collect()
+    }
+
+    #[test]
+    fn reports_what_each_player_ignores_with_its_line() {
+        let reports = check_compatibility(SFZ, &ALL_PLAYERS);
+        let found: Vec<_> = reports
+            .iter()
+            .map(|report| (report.player, lines(report.opcodes()), lines(report.headers()), lines(report.directives())))
+            .collect();
+        let headers = |lines: &[usize]| lines.iter().map(|line| (*line, None)).collect::<Vec<_>>();
+        let unsupported = [(3, Some("sw_label")), (4, Some("polyphony_stealing"))];
+        assert_eq!(
+            found,
+            [
+                (PlayerProfile::Aria, vec![], headers(&[8]), vec![]),
+                (PlayerProfile::Sfizz, vec![], headers(&[7]), vec![]),
+                (
+                    PlayerProfile::Cakewalk,
+                    vec![(2, Some("set_cc7")), (4, Some("polyphony_stealing"))],
+                    headers(&[3, 6, 7, 8]),
+                    vec![(1, Some("#define"))],
+                ),
+                (PlayerProfile::LinuxSampler, unsupported.to_vec(), headers(&[6, 7, 8]), vec![]),
+                (PlayerProfile::BassMidi, unsupported.to_vec(), headers(&[6, 7, 8]), vec![]),
+            ]
+        );
+        assert!(reports.iter().all(|report| !report.is_compatible()));
+    }
+
+    #[test]
+    fn skips_opcodes_of_unsupported_headers() {
+        let reports = check_compatibility("<master> sw_label=x\n<region> sample=a.wav\n", &[PlayerProfile::Cakewalk]);
+        assert_eq!(lines(reports[0].headers()), [(1, None)]);
+        assert_eq!(reports[0].opcodes().count(), 0);
+
+        let reports = check_compatibility("<region> sample=a.wav\n", &ALL_PLAYERS);
+        assert!(reports.iter().all(CompatibilityReport::is_compatible));
+    }
+
+    #[test]
+    fn points_into_included_files() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("main.sfz", "<region> sample=a.wav\n#include \"inc.sfz\"\n");
+        loader.insert("inc.sfz", "<group>\n<region> sample=b.wav sw_label=b\n");
+        let reports = load_compatibility(&Preprocessor::new(loader), "main.sfz", &[PlayerProfile::BassMidi]).unwrap();
+        let issue = reports[0].opcodes().next().unwrap();
+        assert_eq!((issue.file.as_deref(), issue.line), (Some(Path::new("inc.sfz")), 2));
+        assert_eq!(issue.opcode.as_deref(), Some("sw_label"));
+
+        let reports = load_compatibility(&Preprocessor::new(MemoryLoader::new()), "main.sfz", &ALL_PLAYERS);
+        assert_eq!(reports.unwrap_err().kind, SfzErrorKind::Io);
+    }
+}
@@ -1,11 +1,13 @@
-use crate::header_types::Control;
-use crate::parser::{white_space, take_to_newline, parse_identifier, parse_key_value, parse_value};
+use crate::comment::find_comment;
+use crate::header_types::{CcLabel, Control, KeyLabel, OutputLabel};
+use crate::parser::{white_space, parse_key_value};
+use crate::note::MiddleC;
+use crate::validation::{ValueError, ValueRange};
 use nom::{
-    sequence::tuple,
+    sequence::preceded,
     IResult,
-    multi::{many0, many1},
-    bytes::complete::{tag, take_while, take_while1},
-    character::complete::{newline, space1, char, multispace0},
+    bytes::complete::{tag, take_while1},
+    character::complete::{space1, char, multispace0},
 };
 
 use std::path::PathBuf;
@@ -14,37 +16,37 @@ fn is_alphanumeric_underscore(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
 }
 
-fn variable_name(sfz_source: &str) -> IResult<&str, &str> {
+pub fn variable_name(sfz_source: &str) -> IResult<&str, &str> {
     let (remaining, _) = tag("$")(sfz_source)?;
     take_while1(|c: char| c.is_alphanumeric() || c == '_')(remaining)
 }
 
+/// A variable's value runs to the end of the line, so it may hold
+/// numbers such as `-12` or `0.5`, paths and text with spaces.
 fn variable_value(sfz_source: &str) -> IResult<&str, &str> {
-    take_while1(char::is_alphanumeric)(sfz_source)
+    let (_, line) = take_while1(|c: char| c != '\n' && c != '\r')(sfz_source)?;
+    let line = find_comment(line).map_or(line, |(comment, _)| &line[..comment]);
+    let var_value = line.trim_end();
+    if var_value.is_empty() {
+        return Err(nom::Err::Error(nom::error::Error::new(sfz_source, nom::error::ErrorKind::TakeWhile1)));
+    }
+    Ok((&sfz_source[var_value.len()..], var_value))
 }
 
-fn parse_define_line(sfz_source: &str) -> IResult<&str, (&str, &str)> {
+/// Parses `#define $NAME value`, stopping right after the value.
+pub fn parse_define_directive(sfz_source: &str) -> IResult<&str, (&str, &str)> {
     let (remaining, _) = tag("#define")(sfz_source)?;
     let (remaining, _) = space1(remaining)?;
     let (remaining, var_name) = variable_name(remaining)?;
     let (remaining, _) = space1(remaining)?;
     let (remaining, var_value) = variable_value(remaining)?;
-    let (remaining, _) = newline(remaining)?;
-    let (remaining, _) = space1(remaining)?;
     Ok((remaining, (var_name, var_value)))
 }
 
-fn parse_defines(sfz_source: &str) -> IResult<&str, Vec<(&str, &str)>> {
-    many1(parse_define_line)(sfz_source)
-}
-
-pub fn parse_default_path(sfz_source: &str) -> IResult<&str, &str> {
-    let (remaining, _) = parse_identifier(&sfz_source)?;
-    let (remaining, _) = parse_value(remaining)?;
-    let (remaining, output) = parse_key_value(remaining)?;
-
-    let (_, default_path) = output;
-    Ok((remaining, default_path))
+pub fn parse_define_line(sfz_source: &str) -> IResult<&str, (&str, &str)> {
+    let (remaining, define) = parse_define_directive(sfz_source)?;
+    let (remaining, _) = multispace0(remaining)?;
+    Ok((remaining, define))
 }
 
 pub fn add_define_directives(control_header: &mut Control, directives: Vec<(&str, &str)>) {
@@ -60,50 +62,55 @@ fn dequote(sfz_source: &str) -> IResult<&str, &str> {
 
     let (include_var, _) = remaining;
 
+    // Include paths may contain spaces, so take everything up to the closing quote.
     let (remaining, include_directive) =
-        take_while1(|c: char| c.is_alphanumeric() || c == '\\' || c == '.' && c != '\"')(
-            include_var,
-        )?;
+        take_while1(|c: char| c != '"' && c != '\n')(include_var)?;
     Ok((remaining, include_directive))
 }
 
-pub fn parse_include_line(sfz_source: &str) -> IResult<&str, &str> {
+/// Parses `#include "path"`, stopping right after the closing quote.
+pub fn parse_include_directive(sfz_source: &str) -> IResult<&str, &str> {
     let (remaining, _) = tag("#include")(sfz_source)?;
 
     let (remaining, _) = space1(remaining)?;
     let (remaining, include_directive) = dequote(remaining)?;
     let (remaining, _) = char('"')(remaining)?;
-    let (remaining, _) = multispace0(remaining)?;
 
     Ok((remaining, include_directive))
 }
-fn parse_includes(sfz_source: &str) -> IResult<&str, Vec<&str>> {
-    let (remaining, include_directives) = many0(parse_include_line)(sfz_source)?;
 
-    Ok((remaining, include_directives))
+pub fn parse_include_line(sfz_source: &str) -> IResult<&str, &str> {
+    let (remaining, include_directive) = parse_include_directive(sfz_source)?;
+    let (remaining, _) = multispace0(remaining)?;
+
+    Ok((remaining, include_directive))
 }
 
+/// Parses a `<control>` header with its opcodes and directives, which may
+/// come in any order, stopping at the next header or the end of the source.
+/// Offsets out of range are clamped, as `parse_document` does; the parse
+/// fails only at an offset whose value isn't a whole number.
 pub fn parse_control(sfz_source: &str) -> IResult<&str, Control> {
+    let (mut remaining, _) = preceded(white_space, tag("<control>"))(sfz_source)?;
     let mut control_header = Control::new();
-    let (remaining, _) = parse_identifier(sfz_source)?;
-    let (remaining, default_path) = parse_default_path(remaining)?;
-    control_header.default_path = PathBuf::from(default_path);
-
-
-    let (remaining, _) = newline(remaining)?;
-    let (remaining, _) = take_while(|c: char| c.is_whitespace())(remaining)?;
-    let (remaining, directives) = parse_defines(remaining)?;
-
-    add_define_directives(&mut control_header, directives);
-
-    let (remaining, include_directives) = parse_includes(remaining)?;
-    add_include_directives(&mut control_header, include_directives);
-    let (remaining, cc_labels) = parse_cc_labels(remaining)?;
-
-    add_label_ccns(&mut control_header, cc_labels);
-    let (remaining, output) = parse_set_ccns(remaining)?;
-    println!("output: {:?}", output);
-    Ok((remaining, control_header))
+    loop {
+        let (rest, _) = white_space(remaining)?;
+        if let Ok((rest, define)) = parse_define_directive(rest) {
+            add_define_directives(&mut control_header, vec![define]);
+            remaining = rest;
+        } else if let Ok((rest, include)) = parse_include_directive(rest) {
+            add_include_directives(&mut control_header, vec![include]);
+            remaining = rest;
+        } else if let Ok((after, opcode)) = parse_key_value(rest) {
+            let rejected = add_control_opcodes(&mut control_header, vec![opcode]);
+            if rejected.iter().any(|(_, _, error)| matches!(error, ValueError::Invalid { .. })) {
+                return Err(nom::Err::Error(nom::error::Error::new(rest, nom::error::ErrorKind::Verify)));
+            }
+            remaining = after;
+        } else {
+            return Ok((rest, control_header));
+        }
+    }
 }
 
 pub fn add_include_directives(control_header: &mut Control, directives: Vec<&str>) {
@@ -118,62 +125,158 @@ pub fn add_set_ccns(control_header: &mut Control, set_ccns: Vec<(&str, &str)>) {
     }
 }
 
-pub fn add_label_ccns(control_header: &mut Control, label_ccns: Vec<(&str, &str, &str)>) {
-    for cc_tuple in label_ccns {
-
-        let (label, label_number, label_value) = cc_tuple;
-
-        let cc_label = format!("{}{}", label, label_number);
-
-        let mut instrument = String::new();
-        let mut modulation = String::new();
-
-        if label_value.contains(" ") {
-
-            let mut label_value_iter = label_value.split_whitespace();
-
-            instrument = label_value_iter.next().unwrap().to_owned();
-            modulation = label_value_iter.next().unwrap().to_owned();
-            
-            control_header.label_ccn.insert(cc_label.clone(), (instrument, Some(modulation)));
-        }
-
-        control_header.label_ccn.insert(cc_label, (label_value.to_owned(), None));
-
+/// Adds `label_ccN` labels, given as CC numbers and label texts.
+pub fn add_label_ccns(control_header: &mut Control, label_ccns: Vec<(u16, &str)>) {
+    for (cc, text) in label_ccns {
+        control_header.label_ccn.insert(cc, CcLabel { cc, text: text.to_owned() });
     }
 }
 
-pub fn parse_cc_label(sfz_source: &str) -> IResult<&str, (&str, &str, &str)> {
-    let (remaining, _) = take_while(|c: char| c.is_whitespace())(sfz_source)?;
-
-    let (remaining, label_cc) = tag("label_cc")(remaining)?;
-    let (remaining, (label_number, label_value)) = parse_cc_var(remaining)?;
+/// Adds `label_keyN` labels, given as note numbers and label texts.
+pub fn add_label_keys(control_header: &mut Control, label_keys: Vec<(u8, &str)>) {
+    for (key, text) in label_keys {
+        control_header.label_keyn.insert(key, KeyLabel { key, text: text.to_owned() });
+    }
+}
 
-    Ok((remaining, (label_cc, label_number, label_value)))
+/// Adds `label_outputN` labels, given as output numbers and label texts.
+pub fn add_label_outputs(control_header: &mut Control, label_outputs: Vec<(u16, &str)>) {
+    for (output, text) in label_outputs {
+        control_header.label_outputn.insert(output, OutputLabel { output, text: text.to_owned() });
+    }
 }
 
-pub fn parse_cc_labels(sfz_source: &str) -> IResult<&str, Vec<(&str, &str, &str)>> {
-    let (remaining, cc_labels) = many1(parse_cc_label)(sfz_source)?;
+/// Applies the opcodes of a `<control>` header that was parsed as a
+/// generic list of `key=value` pairs. Opcodes a control header can't hold
+/// are left out. Returns the offsets whose values were rejected, with why.
+pub fn add_control_opcodes<'a>(
+    control_header: &mut Control,
+    opcodes: Vec<(&'a str, &'a str)>,
+) -> Vec<(&'a str, &'a str, ValueError)> {
+    let mut rejected = vec![];
+    for (key, value) in opcodes {
+        let index = |prefix: &str| key.strip_prefix(prefix).and_then(|index| index.parse::<u16>().ok());
+        let offset = match key {
+            "default_path" => {
+                control_header.default_path = PathBuf::from(value);
+                Ok(())
+            }
+            "note_offset" => set_offset(&mut control_header.note_offset, value, ValueRange::Semitones),
+            "octave_offset" => set_offset(&mut control_header.octave_offset, value, ValueRange::Octaves),
+            "sw_note_offset" => set_offset(&mut control_header.sw_note_offset, value, ValueRange::Semitones),
+            "sw_octave_offset" => set_offset(&mut control_header.sw_octave_offset, value, ValueRange::Octaves),
+            _ if key.starts_with("hint_") => {
+                control_header.hints.insert(key.to_owned(), value.to_owned());
+                Ok(())
+            }
+            _ => {
+                let note = index("label_key").and_then(|note| u8::try_from(note).ok()).filter(|note| *note <= 127);
+                if let Some(cc) = index("label_cc") {
+                    add_label_ccns(control_header, vec![(cc, value)]);
+                } else if let Some(note) = note {
+                    add_label_keys(control_header, vec![(note, value)]);
+                } else if let Some(output) = index("label_output") {
+                    add_label_outputs(control_header, vec![(output, value)]);
+                } else if index("set_cc").is_some() {
+                    add_set_ccns(control_header, vec![(key, value)]);
+                } else if index("set_hdcc").is_some() {
+                    control_header.set_hdccn.insert(key.to_owned(), value.to_owned());
+                } else if index("set_realcc").is_some() {
+                    control_header.set_realccn.insert(key.to_owned(), value.to_owned());
+                }
+                Ok(())
+            }
+        };
+        if let Err(error) = offset {
+            rejected.push((key, value, error));
+        }
+    }
+    rejected
+}
 
-    Ok((remaining, cc_labels))
+/// Sets a note or octave offset to `value`. Values outside of `range` are
+/// clamped into it, as players do, and reported; values that aren't
+/// whole numbers leave the offset as it was.
+fn set_offset(offset: &mut i8, value: &str, range: ValueRange) -> Result<(), ValueError> {
+    let checked = range.check(value, MiddleC::default());
+    if let Ok(()) | Err(ValueError::OutOfRange { .. }) = checked {
+        *offset = range.clamp(value, MiddleC::default()).parse().unwrap_or(*offset);
+    }
+    checked
 }
 
-pub fn parse_cc_var(sfz_source: &str) -> IResult<&str, (&str, &str), > {
-    let (remaining, (var_name, _,  var_value)) = tuple((parse_identifier, tag("="), take_to_newline))(sfz_source)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_control_opcodes_and_directives_in_any_order() {
+        let sfz_source = "<control> note_offset=2 #define $A 1\n default_path=samples\\\n#include \"a.sfz\"\n\
+                          hint_ram_based=1 set_cc7=100 <region> sample=a.wav";
+        let (remaining, control) = parse_control(sfz_source).unwrap();
+        assert_eq!(remaining, "<region> sample=a.wav");
+        assert_eq!(control.note_offset, 2);
+        assert_eq!(control.default_path, PathBuf::from("samples\\"));
+        assert_eq!(control.define_directives.get("A").map(String::as_str), Some("1"));
+        assert_eq!(control.include_directives.len(), 1);
+        assert_eq!(control.hints.get("hint_ram_based").map(String::as_str), Some("1"));
+        assert_eq!(control.set_ccn.get("set_cc7").map(String::as_str), Some("100"));
+    }
 
-    Ok((remaining, (var_name, var_value)))
+    #[test]
+    fn parses_empty_control_headers() {
+        let (remaining, control) = parse_control("  <control>\n\n<global>").unwrap();
+        assert_eq!(remaining, "<global>");
+        assert!(control.set_ccn.is_empty() && control.define_directives.is_empty());
+        assert!(parse_control("<global>").is_err());
+    }
 
-}
+    #[test]
+    fn clamps_and_reports_offsets_out_of_range() {
+        let mut control = Control::new();
+        let rejected = add_control_opcodes(&mut control, vec![("note_offset", "200"), ("octave_offset", "-11")]);
+        assert_eq!((control.note_offset, control.octave_offset), (127, -10));
+        assert_eq!(
+            rejected,
+            vec![
+                ("note_offset", "200", ValueError::OutOfRange { min: -127.0, max: 127.0 }),
+                ("octave_offset", "-11", ValueError::OutOfRange { min: -10.0, max: 10.0 }),
+            ]
+        );
+    }
 
-pub fn parse_set_ccn(sfz_source: &str) -> IResult<&str, (&str, &str)> {
-    let (remaining, _) = white_space(sfz_source)?;
-    let (remaining, (set_number, set_value, )) = parse_cc_var(remaining)?;
-    println!("set_number: {set_number}, set_value: {set_value}");
-    Ok((remaining, (set_number, set_value)))
-}
+    #[test]
+    fn keeps_offsets_that_arent_numbers_as_they_were() {
+        let mut control = Control::new();
+        let rejected = add_control_opcodes(&mut control, vec![("sw_note_offset", "12"), ("sw_note_offset", "x")]);
+        assert_eq!(control.sw_note_offset, 12);
+        assert!(matches!(rejected[..], [("sw_note_offset", "x", ValueError::Invalid { .. })]));
+        assert!(parse_control("<control> octave_offset=x <region>").is_err());
+    }
 
-pub fn parse_set_ccns(sfz_source: &str) -> IResult<&str, Vec<(&str, &str)>> {
-    let (remaining, set_cc_vars) = many0(parse_set_ccn)(sfz_source)?;
+    #[test]
+    fn clamps_offsets_out_of_range_when_parsing() {
+        let (remaining, control) = parse_control("<control> note_offset=200 octave_offset=-11 <region>").unwrap();
+        assert_eq!(remaining, "<region>");
+        assert_eq!((control.note_offset, control.octave_offset), (127, -10));
+    }
 
-    Ok((remaining, set_cc_vars))
+    #[test]
+    fn keys_labels_by_number() {
+        let mut control = Control::new();
+        let opcodes = vec![
+            ("label_cc74", "Cutoff"),
+            ("label_cc7", "Volume"),
+            ("label_key60", "Middle C"),
+            ("label_key128", "Too high"),
+            ("label_output2", "Room mics"),
+            ("label_cc74", "Brightness"),
+        ];
+        assert!(add_control_opcodes(&mut control, opcodes).is_empty());
+        let ccs: Vec<_> = control.label_ccn.values().map(|label| (label.cc, label.text.as_str())).collect();
+        assert_eq!(ccs, [(7, "Volume"), (74, "Brightness")]);
+        assert_eq!(control.label_keyn.keys().collect::<Vec<_>>(), [&60]);
+        assert_eq!(control.label_keyn[&60].text, "Middle C");
+        assert_eq!(control.label_outputn[&2].text, "Room mics");
+    }
 }
@@ -0,0 +1,280 @@
+use crate::comment::{comment_len, comment_start};
+use crate::control::{parse_define_directive, parse_include_directive};
+use crate::header_types::HeaderType;
+use crate::parser::{is_free_text_opcode, parse_free_text_value, parse_key_value, parse_value};
+use nom::{
+    bytes::complete::{tag, take_while1},
+    combinator::recognize,
+    sequence::delimited,
+    IResult,
+};
+use std::fmt;
+
+/// A piece of an SFZ file, exactly as written. Joining the text of every
+/// node of a `SyntaxTree` gives back the file byte for byte.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyntaxNode {
+    /// Spaces, tabs and line breaks.
+    Whitespace(String),
+    /// A `//` or `/* */` comment, delimiters included.
+    Comment(String),
+    /// A header such as `<region>`, which may be one no player knows.
+    Header(String),
+    /// A `key=value` pair, with the key spelled as written.
+    Opcode { key: String, value: String },
+    /// A `#define` or `#include` directive.
+    Directive(String),
+    /// Text that is neither of the above, kept so nothing is lost.
+    Error(String),
+}
+
+impl SyntaxNode {
+    /// The header's type, if this is a header of a known type.
+    pub fn header_type(&self) -> Option<HeaderType> {
+        match self {
+            SyntaxNode::Header(text) => HeaderType::from_name(&text[1..text.len() - 1]),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxNode::Opcode { key, value } => write!(f, "{key}={value}"),
+            SyntaxNode::Whitespace(text)
+            | SyntaxNode::Comment(text)
+            | SyntaxNode::Header(text)
+            | SyntaxNode::Directive(text)
+            | SyntaxNode::Error(text) => f.write_str(text),
+        }
+    }
+}
+
+/// A lossless syntax tree of an SFZ file: every node in document order,
+/// whitespace and comments included. Opcodes belong to the header before
+/// them. Edits only touch the nodes they're made to, so printing the tree
+/// gives back the file with just those changes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyntaxTree {
+    pub nodes: Vec<SyntaxNode>,
+}
+
+/// Parses `<name>`, whether or not `name` is a known header.
+fn any_header(sfz_source: &str) -> IResult<&str, &str> {
+    recognize(delimited(tag("<"), take_while1(|c: char| c.is_alphanumeric() || c == '_'), tag(">")))(sfz_source)
+}
+
+/// Parses an opcode whose key uses a `#define` variable, as in
+/// `label_cc$MOD=Modulation`. The value is free text if the key is once
+/// the variables are filled in.
+fn templated_key_value(sfz_source: &str) -> IResult<&str, (&str, &str)> {
+    let (remaining, key) = take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '$')(sfz_source)?;
+    let (remaining, _) = tag("=")(remaining)?;
+    let filled: String = key
+        .split('$')
+        .enumerate()
+        .map(|(index, piece)| match index {
+            0 => piece.to_owned(),
+            _ => format!("0{}", piece.trim_start_matches(|c: char| c.is_alphanumeric() || c == '_')),
+        })
+        .collect();
+    let (remaining, value) =
+        if is_free_text_opcode(&filled) { parse_free_text_value(remaining)? } else { parse_value(remaining)? };
+    Ok((remaining, (key, value)))
+}
+
+/// Parses the node at the start of `sfz_source`, which must not be empty.
+fn syntax_node(sfz_source: &str) -> IResult<&str, SyntaxNode> {
+    if let Ok((remaining, white_space)) = take_while1::<_, _, ()>(char::is_whitespace)(sfz_source) {
+        return Ok((remaining, SyntaxNode::Whitespace(white_space.to_owned())));
+    }
+    if let Some(kind) = comment_start(sfz_source) {
+        let (comment, remaining) = sfz_source.split_at(comment_len(sfz_source, kind));
+        return Ok((remaining, SyntaxNode::Comment(comment.to_owned())));
+    }
+    let directive = parse_define_directive(sfz_source)
+        .map(|(remaining, _)| remaining)
+        .or_else(|_| parse_include_directive(sfz_source).map(|(remaining, _)| remaining));
+    if let Ok(remaining) = directive {
+        let directive = &sfz_source[..sfz_source.len() - remaining.len()];
+        return Ok((remaining, SyntaxNode::Directive(directive.to_owned())));
+    }
+    if let Ok((remaining, header)) = any_header(sfz_source) {
+        return Ok((remaining, SyntaxNode::Header(header.to_owned())));
+    }
+    match parse_key_value(sfz_source).or_else(|_| templated_key_value(sfz_source)) {
+        Ok((remaining, (key, value))) if !key.is_empty() => {
+            Ok((remaining, SyntaxNode::Opcode { key: key.to_owned(), value: value.to_owned() }))
+        }
+        _ => {
+            // Skip to the next whitespace, and at least one character.
+            let len = sfz_source
+                .char_indices()
+                .skip(1)
+                .find(|(_, c)| c.is_whitespace())
+                .map_or(sfz_source.len(), |(index, _)| index);
+            let (error, remaining) = sfz_source.split_at(len);
+            Ok((remaining, SyntaxNode::Error(error.to_owned())))
+        }
+    }
+}
+
+/// Parses a whole document into a syntax tree. Never fails: text that
+/// isn't valid SFZ ends up in `SyntaxNode::Error` nodes.
+pub fn parse_syntax_tree(sfz_source: &str) -> SyntaxTree {
+    let mut nodes = vec![];
+    let mut remaining = sfz_source;
+    while !remaining.is_empty() {
+        let Ok((rest, node)) = syntax_node(remaining) else {
+            break;
+        };
+        nodes.push(node);
+        remaining = rest;
+    }
+    SyntaxTree { nodes }
+}
+
+impl SyntaxTree {
+    /// The index and text of every header.
+    pub fn headers(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.nodes.iter().enumerate().filter_map(|(index, node)| match node {
+            SyntaxNode::Header(text) => Some((index, text.as_str())),
+            _ => None,
+        })
+    }
+
+    /// The index, key and value of every opcode.
+    pub fn opcodes(&self) -> impl Iterator<Item = (usize, &str, &str)> {
+        self.nodes.iter().enumerate().filter_map(|(index, node)| match node {
+            SyntaxNode::Opcode { key, value } => Some((index, key.as_str(), value.as_str())),
+            _ => None,
+        })
+    }
+
+    /// The index of the header the node at `index` belongs to.
+    pub fn header_of(&self, index: usize) -> Option<usize> {
+        self.nodes[..index].iter().rposition(|node| matches!(node, SyntaxNode::Header(_)))
+    }
+
+    /// The index of each opcode of the header at `header`, in order.
+    pub fn header_opcodes(&self, header: usize) -> Vec<usize> {
+        self.nodes[header + 1..]
+            .iter()
+            .take_while(|node| !matches!(node, SyntaxNode::Header(_)))
+            .enumerate()
+            .filter(|(_, node)| matches!(node, SyntaxNode::Opcode { .. }))
+            .map(|(offset, _)| header + 1 + offset)
+            .collect()
+    }
+
+    /// The index of the opcode spelled `key` in the header at `header`.
+    pub fn find_opcode(&self, header: usize, key: &str) -> Option<usize> {
+        self.header_opcodes(header).into_iter().find(|index| match &self.nodes[*index] {
+            SyntaxNode::Opcode { key: written, .. } => written == key,
+            _ => false,
+        })
+    }
+
+    /// Replaces the value of the opcode at `index`. Returns `false` if the
+    /// node isn't an opcode.
+    pub fn set_value(&mut self, index: usize, new_value: impl Into<String>) -> bool {
+        match self.nodes.get_mut(index) {
+            Some(SyntaxNode::Opcode { value, .. }) => {
+                *value = new_value.into();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Renames the opcode at `index`. Returns `false` if the node isn't an opcode.
+    pub fn set_key(&mut self, index: usize, new_key: impl Into<String>) -> bool {
+        match self.nodes.get_mut(index) {
+            Some(SyntaxNode::Opcode { key, .. }) => {
+                *key = new_key.into();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds an opcode right after the node at `index`, separated from it by
+    /// a space. Returns the index of the new opcode.
+    pub fn insert_opcode(&mut self, index: usize, key: impl Into<String>, value: impl Into<String>) -> usize {
+        let opcode = SyntaxNode::Opcode { key: key.into(), value: value.into() };
+        self.nodes.splice(index + 1..index + 1, [SyntaxNode::Whitespace(" ".to_owned()), opcode]);
+        index + 2
+    }
+
+    /// Removes the node at `index` along with the spaces separating it from
+    /// its neighbours on the same line, leaving line breaks alone.
+    pub fn remove(&mut self, index: usize) -> SyntaxNode {
+        let same_line = |node: Option<&SyntaxNode>| {
+            matches!(node, Some(SyntaxNode::Whitespace(text)) if !text.contains(['\n', '\r']))
+        };
+        let node = self.nodes.remove(index);
+        if same_line(self.nodes.get(index)) {
+            self.nodes.remove(index);
+        } else if index > 0 && same_line(self.nodes.get(index - 1)) {
+            self.nodes.remove(index - 1);
+        }
+        node
+    }
+}
+
+/// Prints the document back, byte for byte as parsed apart from any edits.
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.nodes.iter().try_for_each(|node| write!(f, "{node}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "// Piano \u{e9}\r\n<control>\tdefault_path=samples\\piano\\\r\n#define $MOD 1\n\
+                         label_cc$MOD=Mod wheel /* pads */ <group>lokey=c4  hikey=d4\n<region> sample=a b.wav\n\
+                         <foo> ??? bad=\n<region>sample=last.wav";
+
+    #[test]
+    fn prints_documents_back_byte_for_byte() {
+        for sfz_source in [MESSY, "", "  \n", "<region>", "/* unterminated", "=5 <region> sample=a.wav"] {
+            assert_eq!(parse_syntax_tree(sfz_source).to_string(), sfz_source);
+        }
+    }
+
+    #[test]
+    fn splits_documents_into_nodes() {
+        let tree = parse_syntax_tree("<group> lokey=c4 // low\n#define $A 1\n???");
+        assert_eq!(
+            tree.nodes,
+            [
+                SyntaxNode::Header("<group>".to_owned()),
+                SyntaxNode::Whitespace(" ".to_owned()),
+                SyntaxNode::Opcode { key: "lokey".to_owned(), value: "c4".to_owned() },
+                SyntaxNode::Whitespace(" ".to_owned()),
+                SyntaxNode::Comment("// low".to_owned()),
+                SyntaxNode::Whitespace("\n".to_owned()),
+                SyntaxNode::Directive("#define $A 1".to_owned()),
+                SyntaxNode::Whitespace("\n".to_owned()),
+                SyntaxNode::Error("???".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn edits_only_the_nodes_they_touch() {
+        let mut tree = parse_syntax_tree(MESSY);
+        let group = tree.headers().find(|(_, text)| *text == "<group>").unwrap().0;
+        let lokey = tree.find_opcode(group, "lokey").unwrap();
+        tree.set_value(lokey, "c3");
+        let hikey = tree.find_opcode(group, "hikey").unwrap();
+        tree.remove(hikey);
+        tree.insert_opcode(lokey, "hivel", "100");
+        let expected = MESSY.replace("lokey=c4  hikey=d4", "lokey=c3 hivel=100");
+        assert_eq!(tree.to_string(), expected);
+        assert_eq!(tree.header_of(lokey), Some(group));
+    }
+}
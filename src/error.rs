@@ -0,0 +1,211 @@
+use crate::header_types::HeaderType;
+use crate::lint::LintRule;
+use crate::preprocessor::{line_column, SourceMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SfzErrorKind {
+    /// Text that is neither a header, an opcode nor a directive.
+    Syntax,
+    /// A header name that isn't part of any SFZ version.
+    UnknownHeader,
+    /// An opcode appearing before the first header.
+    OpcodeOutsideHeader,
+    /// An opcode followed by `=` but no value.
+    MissingValue,
+    /// A file could not be loaded.
+    Io,
+    /// A file includes itself, directly or through other files.
+    IncludeCycle,
+    /// Includes nest deeper than the preprocessor allows.
+    IncludeDepth,
+    /// A `$variable` used while no definition for it was in effect.
+    UndefinedVariable,
+    /// An opcode value that isn't of the opcode's type.
+    InvalidValue,
+    /// An opcode value outside of the opcode's range.
+    OutOfRange,
+    /// An opcode in a header it has no effect in.
+    MisplacedOpcode,
+    /// An opcode the player being parsed for ignores.
+    UnsupportedOpcode,
+    /// A header the player being parsed for ignores, along with its opcodes.
+    UnsupportedHeader,
+    /// A `#define` or `#include` the player being parsed for doesn't expand.
+    UnsupportedDirective,
+    /// Something the linter's rule of that name found.
+    Lint(LintRule),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Text that couldn't be used.
+    Error,
+    /// Text that could be used, but probably doesn't do what was intended.
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// An error or warning located in an SFZ file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SfzError {
+    pub severity: Severity,
+    pub kind: SfzErrorKind,
+    pub message: String,
+    /// The file the error is in, when the source was loaded from one.
+    pub file: Option<PathBuf>,
+    /// 1-based line of the start of `span`.
+    pub line: usize,
+    /// 1-based column, counted in characters, of the start of `span`.
+    pub column: usize,
+    /// Byte range of the offending text within its file.
+    pub span: Range<usize>,
+    /// The header the offending text belongs to, if any.
+    pub header: Option<HeaderType>,
+    /// The opcode the offending text belongs to, if any.
+    pub opcode: Option<String>,
+}
+
+impl SfzError {
+    /// Creates an error for the text at `span` within `sfz_source`.
+    pub fn new(kind: SfzErrorKind, message: impl Into<String>, sfz_source: &str, span: Range<usize>) -> Self {
+        let (line, column) = line_column(sfz_source, span.start);
+        SfzError {
+            severity: Severity::Error,
+            kind,
+            message: message.into(),
+            file: None,
+            line,
+            column,
+            span,
+            header: None,
+            opcode: None,
+        }
+    }
+
+    pub fn as_warning(mut self) -> Self {
+        self.severity = Severity::Warning;
+        self
+    }
+
+    pub fn in_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    pub fn with_header(mut self, header: Option<HeaderType>) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn with_opcode(mut self, opcode: impl Into<String>) -> Self {
+        self.opcode = Some(opcode.into());
+        self
+    }
+
+    /// Moves an error found in preprocessed text to the file and position
+    /// the offending text came from.
+    pub fn relocate(mut self, source_map: &SourceMap) -> Self {
+        let (Some(start), Some(end)) = (
+            source_map.locate(self.span.start),
+            source_map.locate(self.span.end),
+        ) else {
+            return self;
+        };
+        let file = &source_map.files[start.file];
+        // A span crossing into another file is cut short at the end of the first.
+        let end = if end.file == start.file { end.offset.max(start.offset) } else { file.text.len() };
+        (self.line, self.column) = line_column(&file.text, start.offset);
+        self.span = start.offset..end;
+        self.file = Some(file.path.clone());
+        self
+    }
+
+    /// Renders the error with the offending line of `sfz_source`, which must
+    /// be the text of the error's file, and the span underlined with carets.
+    pub fn render(&self, sfz_source: &str) -> String {
+        let line_start = sfz_source[..char_boundary(sfz_source, self.span.start)]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+        let line_end = sfz_source[line_start..]
+            .find(['\r', '\n'])
+            .map_or(sfz_source.len(), |end| line_start + end);
+        let source_line = &sfz_source[line_start..line_end];
+
+        // The span may not fit `sfz_source` if it isn't the text the error
+        // was made from, so keep it within the line.
+        let start = char_boundary(sfz_source, self.span.start.min(line_end));
+        let end = char_boundary(sfz_source, self.span.end.max(start).min(line_end));
+        let underlined = &sfz_source[start..end];
+        let carets = "^".repeat(underlined.chars().count().max(1));
+        let gutter = " ".repeat(self.line.to_string().len());
+        let location = match &self.file {
+            Some(file) => format!("{}:{}:{}", file.display(), self.line, self.column),
+            None => format!("{}:{}", self.line, self.column),
+        };
+
+        let mut rendered = format!("{}: {}\n{gutter}--> {location}\n{gutter} |\n", self.severity.name(), self.message);
+        rendered += &format!("{} | {source_line}\n", self.line);
+        rendered += &format!("{gutter} | {}{carets}\n", " ".repeat(self.column.saturating_sub(1)));
+        rendered
+    }
+}
+
+/// The last character boundary of `text` at or before `offset`.
+fn char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+impl fmt::Display for SfzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}: {}: {}", self.line, self.column, self.severity.name(), self.message)
+    }
+}
+
+impl std::error::Error for SfzError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_errors_by_line_and_column() {
+        let sfz_source = "<region>\n  lokey=x\n";
+        let error = SfzError::new(SfzErrorKind::InvalidValue, "bad key", sfz_source, 17..18);
+        assert_eq!((error.line, error.column), (2, 9));
+        assert_eq!(error.to_string(), "2:9: error: bad key");
+        assert_eq!(error.in_file("a.sfz").as_warning().to_string(), "a.sfz:2:9: warning: bad key");
+    }
+
+    #[test]
+    fn renders_the_line_with_the_span_underlined() {
+        let sfz_source = "<region>\n  lokey=xyz\n";
+        let error = SfzError::new(SfzErrorKind::InvalidValue, "bad key", sfz_source, 17..20);
+        assert_eq!(error.render(sfz_source), "error: bad key\n --> 2:9\n  |\n2 |   lokey=xyz\n  |         ^^^\n");
+    }
+
+    #[test]
+    fn renders_spans_that_dont_fit_the_source() {
+        let error = SfzError::new(SfzErrorKind::Syntax, "oops", "<region> sample=a.wav oops", 22..26);
+        assert_eq!(error.render("<region>"), "error: oops\n --> 1:23\n  |\n1 | <region>\n  |                       ^\n");
+        error.render("");
+        error.render("<région> é");
+    }
+}
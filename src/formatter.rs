@@ -0,0 +1,264 @@
+use crate::control::{parse_define_directive, parse_include_directive};
+use crate::cst::{parse_syntax_tree, SyntaxNode};
+use crate::error::{SfzError, SfzErrorKind};
+use crate::header_types::HeaderType;
+use crate::note::{note_name, note_number, MiddleC};
+use crate::registry::{canonical_name, lookup, opcodes, ValueType};
+use crate::writer::WriteStyle;
+
+/// How values of key opcodes such as `lokey` are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NoteStyle {
+    /// MIDI note numbers, as in `lokey=60`.
+    #[default]
+    Numbers,
+    /// Note names, as in `lokey=c4`.
+    Names,
+}
+
+/// How `format_sfz` lays out a document.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FormatOptions {
+    pub style: WriteStyle,
+    pub notes: NoteStyle,
+    /// The octave note names are read and written with for middle C.
+    pub middle_c: MiddleC,
+}
+
+/// Opcodes whose values are paths.
+const PATH_OPCODES: [&str; 3] = ["sample", "default_path", "image"];
+
+/// One opcode and the comments that go with it.
+struct FormattedOpcode {
+    key: String,
+    value: String,
+    /// Comments on lines of their own right before the opcode.
+    comments: Vec<String>,
+    /// A comment on the same line, after the opcode.
+    trailing: Option<String>,
+}
+
+enum Item {
+    Opcode(FormattedOpcode),
+    /// A comment with no opcode after it, or a directive. Opcodes are
+    /// never moved across either.
+    Line(String),
+}
+
+/// A header and what follows it, or whatever comes before the first header.
+struct Block {
+    /// Comments right before the header.
+    comments: Vec<String>,
+    header: Option<String>,
+    items: Vec<Item>,
+}
+
+/// Formats a document the same way whatever its layout: headers and
+/// opcodes laid out in `options.style`, headers of a new block preceded
+/// by a blank line, opcodes in the order of the opcode registry, which
+/// groups them by category, key values written as `options.notes`, paths
+/// with `/` separators, and no trailing whitespace. Comments stay with
+/// the opcode they precede or follow on the same line. Fails on text that
+/// isn't valid SFZ, rather than moving it around.
+pub fn format_sfz(sfz_source: &str, options: &FormatOptions) -> Result<String, SfzError> {
+    let blocks = blocks(sfz_source, options)?;
+    let mut formatted = String::new();
+    let mut previous_header: Option<&str> = None;
+    for block in &blocks {
+        let mut lines = vec![];
+        let mut line = String::new();
+        if let Some(header) = &block.header {
+            // In compact style, regions follow their group without a break.
+            let same_block = options.style == WriteStyle::Compact && header == "<region>" && previous_header.is_some();
+            if !formatted.is_empty() && !same_block {
+                formatted.push('\n');
+            }
+            previous_header = Some(header);
+            lines.extend(block.comments.iter().cloned());
+            line.push_str(header);
+        }
+        for item in &block.items {
+            match item {
+                Item::Opcode(opcode) => {
+                    let one_per_line = options.style == WriteStyle::OnePerLine;
+                    if one_per_line || !opcode.comments.is_empty() {
+                        end_line(&mut lines, &mut line);
+                    }
+                    lines.extend(opcode.comments.iter().cloned());
+                    if !line.is_empty() {
+                        line.push(' ');
+                    }
+                    line += &format!("{}={}", opcode.key, opcode.value);
+                    if let Some(trailing) = &opcode.trailing {
+                        line += &format!(" {trailing}");
+                        end_line(&mut lines, &mut line);
+                    }
+                }
+                Item::Line(text) => {
+                    end_line(&mut lines, &mut line);
+                    lines.push(text.clone());
+                }
+            }
+        }
+        end_line(&mut lines, &mut line);
+        for line in lines {
+            formatted += &line;
+            formatted.push('\n');
+        }
+    }
+
+    let trimmed: Vec<&str> = formatted.lines().map(str::trim_end).collect();
+    Ok(trimmed.join("\n").trim_matches('\n').to_owned() + "\n")
+}
+
+fn end_line(lines: &mut Vec<String>, line: &mut String) {
+    if !line.is_empty() {
+        lines.push(std::mem::take(line));
+    }
+}
+
+/// Splits a document into blocks, attaching comments to their opcodes,
+/// and puts the opcodes between directives in registry order.
+fn blocks(sfz_source: &str, options: &FormatOptions) -> Result<Vec<Block>, SfzError> {
+    let tree = parse_syntax_tree(sfz_source);
+    let mut blocks = vec![Block { comments: vec![], header: None, items: vec![] }];
+    let mut comments: Vec<String> = vec![];
+    // Whether the last opcode is still on the current line.
+    let mut after_opcode = false;
+    let mut offset = 0;
+
+    for node in &tree.nodes {
+        let len = node.to_string().len();
+        let block = blocks.last_mut().expect("there is always a block");
+        match node {
+            SyntaxNode::Whitespace(text) => after_opcode &= !text.contains(['\n', '\r']),
+            SyntaxNode::Comment(text) => match block.items.last_mut() {
+                Some(Item::Opcode(opcode)) if after_opcode => {
+                    let trailing = opcode.trailing.get_or_insert_with(String::new);
+                    if !trailing.is_empty() {
+                        trailing.push(' ');
+                    }
+                    trailing.push_str(text.trim_end());
+                }
+                _ => comments.push(text.trim_end().to_owned()),
+            },
+            SyntaxNode::Header(text) => {
+                let comments = std::mem::take(&mut comments);
+                blocks.push(Block { comments, header: Some(text.clone()), items: vec![] });
+                after_opcode = false;
+            }
+            SyntaxNode::Opcode { key, value } => {
+                block.items.push(Item::Opcode(FormattedOpcode {
+                    key: key.clone(),
+                    value: format_value(key, value, options),
+                    comments: std::mem::take(&mut comments),
+                    trailing: None,
+                }));
+                after_opcode = true;
+            }
+            SyntaxNode::Directive(text) => {
+                block.items.extend(comments.drain(..).map(Item::Line));
+                block.items.push(Item::Line(format_directive(text)));
+                after_opcode = false;
+            }
+            SyntaxNode::Error(text) => {
+                let header = block.header.as_deref().and_then(|header| HeaderType::from_name(header.trim_matches(['<', '>'])));
+                return Err(SfzError::new(
+                    SfzErrorKind::Syntax,
+                    format!("can't format `{text}`, which is neither a header, an opcode nor a directive"),
+                    sfz_source,
+                    offset..offset + len,
+                )
+                .with_header(header));
+            }
+        }
+        offset += len;
+    }
+    let block = blocks.last_mut().expect("there is always a block");
+    block.items.extend(comments.drain(..).map(Item::Line));
+
+    for block in &mut blocks {
+        for run in block.items.split_mut(|item| matches!(item, Item::Line(_))) {
+            run.sort_by_key(|item| match item {
+                Item::Opcode(opcode) => registry_position(&opcode.key),
+                Item::Line(_) => usize::MAX,
+            });
+        }
+    }
+    Ok(blocks)
+}
+
+/// Where an opcode is in the registry. Aliases sort with the opcode they
+/// stand for; opcodes the registry doesn't know go last.
+fn registry_position(key: &str) -> usize {
+    lookup(&canonical_name(key))
+        .and_then(|info| opcodes().iter().position(|other| std::ptr::eq(other, info)))
+        .unwrap_or(usize::MAX)
+}
+
+fn format_value(key: &str, value: &str, options: &FormatOptions) -> String {
+    if PATH_OPCODES.contains(&key) {
+        return value.replace('\\', "/");
+    }
+    let is_note = lookup(key).is_some_and(|info| info.value_type == ValueType::Note);
+    match note_number(value, options.middle_c) {
+        Some(note) if is_note => match options.notes {
+            NoteStyle::Numbers => note.to_string(),
+            NoteStyle::Names => note_name(note, options.middle_c),
+        },
+        _ => value.to_owned(),
+    }
+}
+
+/// Writes a directive with single spaces, and `/` separators in include paths.
+fn format_directive(text: &str) -> String {
+    if let Ok((_, (name, value))) = parse_define_directive(text) {
+        return format!("#define ${name} {value}");
+    }
+    if let Ok((_, include)) = parse_include_directive(text) {
+        return format!("#include \"{}\"", include.replace('\\', "/"));
+    }
+    text.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "// piano\n<control>  default_path=samples\\piano\\ \n#define $KEY 60\n\
+                         <group> hikey=$KEY lokey=c4 // keys\n\n\n<region>sample=a.wav   pitch_keycenter=60\n\
+                         <region> sample=b.wav\n";
+
+    #[test]
+    fn formats_idempotently() {
+        for style in [WriteStyle::OnePerLine, WriteStyle::Compact] {
+            for notes in [NoteStyle::Numbers, NoteStyle::Names] {
+                let options = FormatOptions { style, notes, ..FormatOptions::default() };
+                let formatted = format_sfz(MESSY, &options).unwrap();
+                assert_eq!(format_sfz(&formatted, &options).unwrap(), formatted);
+            }
+        }
+    }
+
+    #[test]
+    fn sorts_aliases_with_the_opcodes_they_stand_for() {
+        let sfz_source = "<region> zzz=1 gain_cc7=3 loopmode=one_shot sample=a.wav\n";
+        assert_eq!(format_sfz(sfz_source, &FormatOptions::default()).unwrap(), "<region>\nsample=a.wav\nloopmode=one_shot\ngain_cc7=3\nzzz=1\n");
+    }
+
+    #[test]
+    fn keeps_comments_with_their_opcodes() {
+        let formatted = format_sfz(MESSY, &FormatOptions::default()).unwrap();
+        assert_eq!(
+            formatted,
+            "// piano\n<control>\ndefault_path=samples/piano/\n#define $KEY 60\n\n\
+             <group>\nlokey=60 // keys\nhikey=$KEY\n\n<region>\nsample=a.wav\npitch_keycenter=60\n\n<region>\nsample=b.wav\n"
+        );
+    }
+
+    #[test]
+    fn refuses_to_format_syntax_errors() {
+        let error = format_sfz("<region> sample=a.wav\n???\n", &FormatOptions::default()).unwrap_err();
+        assert_eq!((error.kind, error.line), (SfzErrorKind::Syntax, 2));
+    }
+}
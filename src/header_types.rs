@@ -1,13 +1,17 @@
 use crate::opcode_types::{BusOption, EffectType};
-use crate::region::Region;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 #[derive(Clone, Debug)]
 pub struct Group<T> {
-    /// The regions to which the common parameters will be applied.
-    regions: Vec<Region>,
+    /// Indices into `SfzInstrument::region` of the regions
+    /// to which the common parameters will be applied.
+    pub regions: Vec<usize>,
     /// The common parameters for the regions associated with the group.
-    common_params: HashMap<String, T>,
+    pub common_params: HashMap<String, T>,
+    /// The headers enclosing this group.
+    pub scope: HeaderScope,
+    /// Opcodes written with an alias, from their canonical name to the spelling used.
+    pub spellings: HashMap<String, String>,
 }
 // Eq
 // PartialEq
@@ -29,7 +33,9 @@ pub struct Group<T> {
 // Default
 #[derive(Clone, Debug)]
 pub struct Global<T> {
-    common_params: HashMap<String, T>,
+    pub common_params: HashMap<String, T>,
+    /// Opcodes written with an alias, from their canonical name to the spelling used.
+    pub spellings: HashMap<String, String>,
 }
 // Copy
 // Clone
@@ -43,8 +49,8 @@ pub struct Global<T> {
 // Default
 #[derive(Clone, Debug)]
 pub struct Curve<T> {
-    index: u32,
-    values: Vec<(String, T)>,
+    pub index: u32,
+    pub values: Vec<(String, T)>,
 }
 
 // Copy
@@ -59,14 +65,33 @@ pub struct Curve<T> {
 // Default
 #[derive(Clone, Debug)]
 pub struct Effect {
-    effect_type: EffectType,
-    param_offset: u32,
-    effect_one: f32,   // Reverb in Cakewalk
-    effect_two: f32,   // Chorus in Cakewalk
-    effect_three: f32, // Gain of regions send tracks into 3rd effect bus.
-    effect_four: f32,  // Gain of regions send tracks into 4th effect bus.
-    bus: BusOption,
-    dsp_order: u8,
+    /// `None` when the `type` opcode is missing or names an effect we don't know.
+    pub effect_type: Option<EffectType>,
+    pub param_offset: u32,
+    pub effect_one: f32,   // Reverb in Cakewalk
+    pub effect_two: f32,   // Chorus in Cakewalk
+    pub effect_three: f32, // Gain of regions send tracks into 3rd effect bus.
+    pub effect_four: f32,  // Gain of regions send tracks into 4th effect bus.
+    pub bus: BusOption,
+    pub dsp_order: u8,
+    /// Effect specific opcodes, such as `reverb_size` or `filter_cutoff`.
+    pub parameters: HashMap<String, String>,
+}
+
+impl Default for Effect {
+    fn default() -> Self {
+        Self {
+            effect_type: None,
+            param_offset: 0,
+            effect_one: 0.0,
+            effect_two: 0.0,
+            effect_three: 0.0,
+            effect_four: 0.0,
+            bus: BusOption::Main,
+            dsp_order: 0,
+            parameters: HashMap::new(),
+        }
+    }
 }
 
 // Copy
@@ -81,8 +106,8 @@ pub struct Effect {
 // Default
 #[derive(Clone, Debug)]
 pub struct Sample {
-    name: PathBuf,
-    data: Vec<u8>,
+    pub name: PathBuf,
+    pub data: Vec<u8>,
 }
 // Copy
 // Eq
@@ -95,8 +120,30 @@ pub struct Sample {
 
 #[derive(Clone, Debug)]
 pub struct Master<T> {
-    op_codes: Vec<(String, T)>,
-    groups: Option<Vec<Group<T>>>,
+    pub op_codes: Vec<(String, T)>,
+    /// Indices into `SfzInstrument::group` of the groups following this master.
+    pub groups: Vec<usize>,
+    /// The headers enclosing this master.
+    pub scope: HeaderScope,
+    /// Opcodes written with an alias, from their canonical name to the spelling used.
+    pub spellings: HashMap<String, String>,
+}
+
+/// ARIA's `<midi>` header, holding opcodes that affect how MIDI is
+/// handled for the whole instrument.
+#[derive(Clone, Debug)]
+pub struct Midi<T> {
+    pub op_codes: Vec<(String, T)>,
+}
+
+/// Indices of the headers in effect at the point a header was parsed,
+/// into the corresponding vectors of `SfzInstrument`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeaderScope {
+    pub control: Option<usize>,
+    pub global: Option<usize>,
+    pub master: Option<usize>,
+    pub group: Option<usize>,
 }
 
 #[derive(Clone, Debug)]
@@ -108,23 +155,59 @@ pub struct Control {
     /// Path to samples, should be relative for Cakewalk,
     /// but can be relative or absolute for ARIA, Bassmidi and sfizz.
     /// The value of `default_path` is reset by a new control header,
-    /// in ARIA, but t in Cakewalk.
+    /// in ARIA, but not in Cakewalk. See `SfzInstrument::resolve_control`.
     pub default_path: PathBuf,
     /// Informs an SFZ player to offset incoming MIDI notes
-    /// by a specified number of semitones.
-    pub note_offset: u8,
+    /// by a specified number of semitones, from -127 to 127.
+    pub note_offset: i8,
     /// Informs an SFZ player to offset incoming MIDI notes
-    /// by a specified number of octaves.
-    pub octave_offset: u8, // Refactor: use https://docs.rs/refinement/latest/refinement/#
+    /// by a specified number of octaves, from -10 to 10.
+    pub octave_offset: i8,
+    /// Like `note_offset`, for the notes keyswitches are played with.
+    pub sw_note_offset: i8,
+    /// Like `octave_offset`, for the notes keyswitches are played with.
+    pub sw_octave_offset: i8,
     /// Creates labels for MIDI control changes for altering parameters
-    /// on MIDI-enabled devices.
-    pub label_ccn: HashMap<String, (String, Option<String>)>, // Refactor: Choose better representation.
+    /// on MIDI-enabled devices, by CC number.
+    pub label_ccn: BTreeMap<u16, CcLabel>,
     /// Sets default values for MIDI CC number N.
     pub set_ccn: HashMap<String, String>,
+    /// Sets default values for high definition MIDI CC number N, from 0 to 1.
+    pub set_hdccn: HashMap<String, String>,
+    /// Sets default values for MIDI CC number N, unlike `set_hdccN`
+    /// without a player's smoothing or curve applied.
+    pub set_realccn: HashMap<String, String>,
+    /// Names MIDI notes as shown on a player's keyboard, by note number.
+    pub label_keyn: BTreeMap<u8, KeyLabel>,
+    /// Names the outputs of a player with several, by output number.
+    pub label_outputn: BTreeMap<u16, OutputLabel>,
+    /// Player specific `hint_*` settings, such as `hint_ram_based`.
+    pub hints: HashMap<String, String>,
     /// Sets include directives for additional SFZ files
     pub include_directives: Vec<String>
 }
 
+/// The name `label_ccN` gives MIDI CC `cc`, as written, spaces and all.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CcLabel {
+    pub cc: u16,
+    pub text: String,
+}
+
+/// The name `label_keyN` gives MIDI note `key`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyLabel {
+    pub key: u8,
+    pub text: String,
+}
+
+/// The name `label_outputN` gives output `output`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputLabel {
+    pub output: u16,
+    pub text: String,
+}
+
 impl Control {
     pub fn new() -> Self {
         Control::default()
@@ -139,8 +222,15 @@ impl Default for Control {
             default_path: PathBuf::new(),
             note_offset: 0,
             octave_offset: 0,
-            label_ccn: HashMap::new(),
+            sw_note_offset: 0,
+            sw_octave_offset: 0,
+            label_ccn: BTreeMap::new(),
             set_ccn: HashMap::new(),
+            set_hdccn: HashMap::new(),
+            set_realccn: HashMap::new(),
+            label_keyn: BTreeMap::new(),
+            label_outputn: BTreeMap::new(),
+            hints: HashMap::new(),
             include_directives: vec![]
         }
     }
@@ -153,7 +243,7 @@ impl Default for Control {
 // Display
 // Default
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum HeaderType {
     Region,
     Group,
@@ -165,3 +255,36 @@ pub enum HeaderType {
     Midi,
     Sample,
 }
+
+impl HeaderType {
+    /// Maps the name between the angle brackets of a header, e.g. `region`
+    /// for `<region>`, to its header type.
+    pub fn from_name(name: &str) -> Option<HeaderType> {
+        match name {
+            "region" => Some(HeaderType::Region),
+            "group" => Some(HeaderType::Group),
+            "control" => Some(HeaderType::Control),
+            "global" => Some(HeaderType::Global),
+            "curve" => Some(HeaderType::Curve),
+            "effect" => Some(HeaderType::Effect),
+            "master" => Some(HeaderType::Master),
+            "midi" => Some(HeaderType::Midi),
+            "sample" => Some(HeaderType::Sample),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeaderType::Region => "region",
+            HeaderType::Group => "group",
+            HeaderType::Control => "control",
+            HeaderType::Global => "global",
+            HeaderType::Curve => "curve",
+            HeaderType::Effect => "effect",
+            HeaderType::Master => "master",
+            HeaderType::Midi => "midi",
+            HeaderType::Sample => "sample",
+        }
+    }
+}
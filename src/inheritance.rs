@@ -0,0 +1,273 @@
+use crate::header_types::HeaderScope;
+use crate::instrument::SfzInstrument;
+use crate::note::KeyTranslation;
+use crate::opcode::{typed_opcodes, Opcode};
+use crate::opcode_key::OpcodeKey;
+use crate::profile::PlayerProfile;
+use crate::refinements::{OneTwentySevenNegToPos, TenNegToPos};
+use crate::validation::refined;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The header that supplied an opcode's effective value, holding its
+/// index into the corresponding vector of `SfzInstrument`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpcodeSource {
+    Global(usize),
+    Master(usize),
+    Group(usize),
+    Region(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedOpcode {
+    pub value: String,
+    pub source: OpcodeSource,
+}
+
+/// The opcodes a region ends up with once the opcodes of its enclosing
+/// `<global>`, `<master>` and `<group>` headers have been applied, in
+/// that order, with the region's own opcodes applied last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedRegion {
+    /// Index into `SfzInstrument::region`.
+    pub region: usize,
+    pub opcodes: HashMap<String, ResolvedOpcode>,
+}
+
+impl ResolvedRegion {
+    pub fn get(&self, opcode: &str) -> Option<&str> {
+        self.opcodes.get(opcode).map(|resolved| resolved.value.as_str())
+    }
+
+    pub fn source(&self, opcode: &str) -> Option<OpcodeSource> {
+        self.opcodes.get(opcode).map(|resolved| resolved.source)
+    }
+
+    /// The effective opcodes as typed opcodes, ordered by key.
+    pub fn typed_opcodes(&self) -> Vec<(OpcodeKey, Opcode)> {
+        typed_opcodes(self.opcodes.iter().map(|(key, resolved)| (key, &resolved.value)))
+    }
+
+    fn apply<'a>(&mut self, opcodes: impl IntoIterator<Item = (&'a String, &'a String)>, source: OpcodeSource) {
+        for (key, value) in opcodes {
+            self.opcodes.insert(
+                key.clone(),
+                ResolvedOpcode { value: value.clone(), source },
+            );
+        }
+    }
+}
+
+/// The `<control>` settings a player applies to a region. Only the
+/// settings of the last control header before the region count, except
+/// that players which don't reset `default_path` on a new control header
+/// keep the last one set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResolvedControl {
+    /// Index into `SfzInstrument::control`, `None` for regions before
+    /// any control header.
+    pub control: Option<usize>,
+    pub default_path: PathBuf,
+    pub note_offset: i8,
+    pub octave_offset: i8,
+    pub sw_note_offset: i8,
+    pub sw_octave_offset: i8,
+}
+
+impl ResolvedControl {
+    pub fn key_translation(&self) -> KeyTranslation {
+        KeyTranslation {
+            note_offset: self.note_offset,
+            octave_offset: self.octave_offset,
+            sw_note_offset: self.sw_note_offset,
+            sw_octave_offset: self.sw_octave_offset,
+        }
+    }
+}
+
+impl<T> SfzInstrument<T> {
+    /// Resolves the control settings `player` applies to the region at
+    /// `index`, or `None` if there is no such region.
+    pub fn resolve_control(&self, index: usize, player: PlayerProfile) -> Option<ResolvedControl> {
+        let control = self.region.get(index)?.scope.control;
+        let default_path = player.default_path(&self.control, control).to_path_buf();
+        let Some(header) = control.map(|control| &self.control[control]) else {
+            return Some(ResolvedControl { default_path, ..ResolvedControl::default() });
+        };
+        Some(ResolvedControl {
+            control,
+            default_path,
+            note_offset: header.note_offset,
+            octave_offset: header.octave_offset,
+            sw_note_offset: header.sw_note_offset,
+            sw_octave_offset: header.sw_octave_offset,
+        })
+    }
+
+    /// Where `player` looks for the sample of the region at `index`,
+    /// relative to the instrument file. `None` if there is no such region,
+    /// it has no sample, or the player doesn't allow its absolute path.
+    pub fn sample_path(&self, index: usize, player: PlayerProfile) -> Option<PathBuf> {
+        let region = self.region.get(index).filter(|region| !region.sample.is_empty())?;
+        let control = self.resolve_control(index, player)?;
+        player.sample_path(&control.default_path, &region.sample)
+    }
+}
+
+impl SfzInstrument<String> {
+    /// The opcodes of the `<global>`, `<master>` and `<group>` headers in
+    /// `scope`, in the order they apply to a region, each with its header.
+    pub(crate) fn enclosing_opcodes(&self, scope: HeaderScope) -> Vec<(OpcodeSource, Vec<(&String, &String)>)> {
+        let mut enclosing = vec![];
+        if let Some(global) = scope.global {
+            enclosing.push((OpcodeSource::Global(global), self.global[global].common_params.iter().collect()));
+        }
+        if let (Some(master), Some(masters)) = (scope.master, self.master.as_ref()) {
+            let op_codes = masters[master].op_codes.iter().map(|(key, value)| (key, value)).collect();
+            enclosing.push((OpcodeSource::Master(master), op_codes));
+        }
+        if let Some(group) = scope.group {
+            enclosing.push((OpcodeSource::Group(group), self.group[group].common_params.iter().collect()));
+        }
+        enclosing
+    }
+
+    /// Resolves the effective opcodes of the region at `index`,
+    /// or `None` if there is no such region.
+    pub fn resolve_region(&self, index: usize) -> Option<ResolvedRegion> {
+        let region = self.region.get(index)?;
+        let mut resolved = ResolvedRegion { region: index, opcodes: HashMap::new() };
+        for (source, opcodes) in self.enclosing_opcodes(region.scope) {
+            resolved.apply(opcodes, source);
+        }
+        resolved.apply(&region.parameters, OpcodeSource::Region(index));
+        Some(resolved)
+    }
+
+    /// How `player` translates incoming notes for the region at `index`,
+    /// or `None` if there is no such region. `sw_note_offset` and
+    /// `sw_octave_offset` set on the region or its enclosing headers take
+    /// the place of the control header's.
+    pub fn key_translation(&self, index: usize, player: PlayerProfile) -> Option<KeyTranslation> {
+        let mut translation = self.resolve_control(index, player)?.key_translation();
+        let region = self.resolve_region(index)?;
+        if let Some(Ok((_, offset))) = region.get("sw_note_offset").map(refined::<i8, OneTwentySevenNegToPos>) {
+            translation.sw_note_offset = *offset;
+        }
+        if let Some(Ok((_, offset))) = region.get("sw_octave_offset").map(refined::<i8, TenNegToPos>) {
+            translation.sw_octave_offset = *offset;
+        }
+        Some(translation)
+    }
+
+    /// Resolves the effective opcodes of every region, in document order.
+    pub fn resolve_regions(&self) -> Vec<ResolvedRegion> {
+        (0..self.region.len())
+            .filter_map(|index| self.resolve_region(index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::parse_sfz;
+
+    const INSTRUMENT: &str = "<global> volume=-6 pan=10
+<master> pan=20 ampeg_release=1
+<group> ampeg_release=2 lokey=40
+<region> sample=a.wav lokey=48
+<group>
+<region> sample=b.wav volume=-3
+";
+
+    #[test]
+    fn resolves_opcodes_from_the_innermost_header() {
+        let instrument = parse_sfz(INSTRUMENT).unwrap();
+        let region = instrument.resolve_region(0).unwrap();
+        assert_eq!(region.get("volume"), Some("-6"));
+        assert_eq!(region.get("pan"), Some("20"));
+        assert_eq!(region.get("ampeg_release"), Some("2"));
+        assert_eq!(region.get("lokey"), Some("48"));
+        assert_eq!(region.get("hikey"), None);
+    }
+
+    #[test]
+    fn records_where_each_value_came_from() {
+        let instrument = parse_sfz(INSTRUMENT).unwrap();
+        let first = instrument.resolve_region(0).unwrap();
+        assert_eq!(first.source("volume"), Some(OpcodeSource::Global(0)));
+        assert_eq!(first.source("pan"), Some(OpcodeSource::Master(0)));
+        assert_eq!(first.source("ampeg_release"), Some(OpcodeSource::Group(0)));
+        assert_eq!(first.source("sample"), Some(OpcodeSource::Region(0)));
+
+        let second = instrument.resolve_region(1).unwrap();
+        assert_eq!(second.source("volume"), Some(OpcodeSource::Region(1)));
+        assert_eq!(second.source("ampeg_release"), Some(OpcodeSource::Master(0)));
+        assert_eq!(second.source("lokey"), None);
+    }
+
+    #[test]
+    fn derives_region_fields_from_the_same_headers() {
+        let instrument = parse_sfz(INSTRUMENT).unwrap();
+        for (index, region) in instrument.region.iter().enumerate() {
+            let resolved = instrument.resolve_region(index).unwrap();
+            let low_key = resolved.get("lokey").map_or(0, |lokey| lokey.parse().unwrap());
+            let volume = resolved.get("volume").map_or(0.0, |volume| volume.parse().unwrap());
+            assert_eq!((region.low_key, region.volume), (low_key, volume));
+        }
+
+        // A region's `key` replaces the `lokey` of its group rather than being overridden by it.
+        let instrument = parse_sfz("<group> lokey=40 <region> key=50").unwrap();
+        assert_eq!((instrument.region[0].low_key, instrument.region[0].high_key), (50, 50));
+    }
+
+    #[test]
+    fn resolves_every_region_in_order() {
+        let instrument = parse_sfz(INSTRUMENT).unwrap();
+        let regions = instrument.resolve_regions();
+        let samples: Vec<_> = regions.iter().map(|region| region.get("sample")).collect();
+        assert_eq!(samples, [Some("a.wav"), Some("b.wav")]);
+        assert!(instrument.resolve_region(2).is_none());
+    }
+
+    #[test]
+    fn resolves_the_control_header_before_each_region() {
+        let instrument = parse_sfz(
+            "<region> sample=a.wav\n<control> default_path=one\\ note_offset=2\n<region> sample=b.wav\n\
+             <control> note_offset=3\n<region> sample=sub\\c.wav",
+        )
+        .unwrap();
+        let control = |index, player| instrument.resolve_control(index, player).unwrap();
+        assert_eq!(control(0, PlayerProfile::Aria), ResolvedControl::default());
+        assert_eq!(control(1, PlayerProfile::Aria).control, Some(0));
+        assert_eq!(control(1, PlayerProfile::Aria).note_offset, 2);
+        assert_eq!(control(2, PlayerProfile::Aria).note_offset, 3);
+        assert_eq!(control(2, PlayerProfile::Aria).default_path, PathBuf::new());
+        assert_eq!(control(2, PlayerProfile::LinuxSampler).default_path, PathBuf::from("one\\"));
+        assert!(instrument.resolve_control(3, PlayerProfile::Aria).is_none());
+    }
+
+    #[test]
+    fn finds_samples_below_the_default_path() {
+        let sfz_source = "<control> default_path=one\\ <region> sample=sub\\a.wav <region> sample=/abs.wav";
+        let instrument = parse_sfz(sfz_source).unwrap();
+        assert_eq!(instrument.sample_path(0, PlayerProfile::Sfizz), Some(PathBuf::from("one/sub/a.wav")));
+        assert_eq!(instrument.sample_path(1, PlayerProfile::Sfizz), Some(PathBuf::from("/abs.wav")));
+        assert_eq!(instrument.sample_path(1, PlayerProfile::Cakewalk), None);
+    }
+
+    #[test]
+    fn lets_regions_override_the_keyswitch_offsets() {
+        let instrument = parse_sfz(
+            "<control> note_offset=-12 sw_note_offset=1 sw_octave_offset=1\n<region> sample=a.wav\n\
+             <group> sw_octave_offset=-1 <region> sample=b.wav sw_note_offset=2",
+        )
+        .unwrap();
+        let first = instrument.key_translation(0, PlayerProfile::Sfizz).unwrap();
+        assert_eq!((first.key(60), first.keyswitch(24)), (Some(48), Some(37)));
+        let second = instrument.key_translation(1, PlayerProfile::Sfizz).unwrap();
+        assert_eq!((second.key(60), second.keyswitch(24)), (Some(48), Some(14)));
+    }
+}
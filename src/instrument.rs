@@ -0,0 +1,704 @@
+use crate::control::{add_control_opcodes, add_define_directives, add_include_directives, parse_define_line, parse_include_line};
+use crate::error::{Severity, SfzError, SfzErrorKind};
+use crate::header_types::{
+    Control, Curve, Effect, Global, Group, HeaderScope, HeaderType, Master, Midi, Sample,
+};
+use crate::note::MiddleC;
+use crate::opcode_types::{BusOption, EffectType};
+use crate::parser::{parse_header, parse_key_value, white_space};
+use crate::preprocessor::{FileLoader, Preprocessed, Preprocessor};
+use crate::profile::PlayerProfile;
+use crate::region::Region;
+use crate::registry::{canonical_name, lookup};
+use crate::validation::{validate_opcode, RangePolicy, ValueError};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A whole SFZ document. Every header is stored in the order it appeared,
+/// and groups, masters and regions record the headers enclosing them
+/// through their `scope`.
+#[derive(Clone, Debug)]
+pub struct SfzInstrument<T> {
+    pub global: Vec<Global<T>>,
+    pub control: Vec<Control>,
+    pub group: Vec<Group<T>>,
+    pub master: Option<Vec<Master<T>>>,
+    pub region: Vec<Region>,
+    pub curve: Vec<Curve<T>>,
+    pub effect: Vec<Effect>,
+    pub midi: Vec<Midi<T>>,
+    pub sample: Vec<Sample>,
+}
+
+impl<T> Default for SfzInstrument<T> {
+    fn default() -> Self {
+        Self {
+            global: vec![],
+            control: vec![],
+            group: vec![],
+            master: None,
+            region: vec![],
+            curve: vec![],
+            effect: vec![],
+            midi: vec![],
+            sample: vec![],
+        }
+    }
+}
+
+impl SfzInstrument<String> {
+    fn close_header(&mut self, scope: &mut HeaderScope, header: OpenHeader, middle_c: MiddleC) {
+        let opcodes = header.opcodes.iter().map(|(key, value)| (key.as_ref(), value.as_ref())).collect();
+        self.push_header(scope, header.header_type, opcodes, header.spellings, middle_c);
+        if header.header_type == HeaderType::Control {
+            let control_header = self.control.last_mut().expect("control header was just pushed");
+            add_define_directives(control_header, header.define_directives);
+            add_include_directives(control_header, header.include_directives);
+        } else if header.header_type == HeaderType::Region {
+            self.region.last_mut().expect("region was just pushed").span = header.span;
+        }
+    }
+
+    /// Adds a header and its opcodes, tracking which control, global,
+    /// master and group headers are in effect for those that follow.
+    /// Note names in a region's key opcodes are read with `middle_c`.
+    fn push_header(
+        &mut self,
+        scope: &mut HeaderScope,
+        header_type: HeaderType,
+        opcodes: Vec<(&str, &str)>,
+        spellings: HashMap<String, String>,
+        middle_c: MiddleC,
+    ) {
+        match header_type {
+            HeaderType::Control => {
+                let mut control_header = Control::new();
+                // Offsets it rejects were reported by `validate_opcode`
+                // when they were read, and are clamped like other values.
+                add_control_opcodes(&mut control_header, opcodes);
+                scope.control = Some(self.control.len());
+                self.control.push(control_header);
+            }
+            HeaderType::Global => {
+                scope.global = Some(self.global.len());
+                scope.master = None;
+                scope.group = None;
+                self.global.push(Global { common_params: to_param_map(opcodes), spellings });
+            }
+            HeaderType::Master => {
+                let masters = self.master.get_or_insert_with(Vec::new);
+                masters.push(Master {
+                    op_codes: to_param_list(opcodes),
+                    groups: vec![],
+                    scope: *scope,
+                    spellings,
+                });
+                scope.master = Some(masters.len() - 1);
+                scope.group = None;
+            }
+            HeaderType::Group => {
+                let group_index = self.group.len();
+                if let (Some(master), Some(masters)) = (scope.master, self.master.as_mut()) {
+                    masters[master].groups.push(group_index);
+                }
+                self.group.push(Group {
+                    regions: vec![],
+                    common_params: to_param_map(opcodes),
+                    scope: *scope,
+                    spellings,
+                });
+                scope.group = Some(group_index);
+            }
+            HeaderType::Region => {
+                if let Some(group) = scope.group {
+                    self.group[group].regions.push(self.region.len());
+                }
+                // Fields inherit from the enclosing headers in the order
+                // `resolve_region` applies them; the region's own opcodes come last.
+                let parameters = to_param_map(opcodes);
+                let mut region = Region::new(HashMap::new(), middle_c);
+                for (_, opcodes) in self.enclosing_opcodes(*scope) {
+                    region.apply_opcodes(opcodes, middle_c);
+                }
+                region.apply_opcodes(&parameters, middle_c);
+                region.parameters = parameters;
+                region.scope = *scope;
+                region.spellings = spellings;
+                self.region.push(region);
+            }
+            HeaderType::Curve => {
+                let mut curve = Curve { index: 0, values: vec![] };
+                for (key, value) in opcodes {
+                    match (key, value.parse()) {
+                        ("curve_index", Ok(index)) => curve.index = index,
+                        _ => curve.values.push((key.to_owned(), value.to_owned())),
+                    }
+                }
+                self.curve.push(curve);
+            }
+            HeaderType::Effect => self.effect.push(effect_from_opcodes(opcodes)),
+            HeaderType::Midi => self.midi.push(Midi { op_codes: to_param_list(opcodes) }),
+            HeaderType::Sample => {
+                let mut sample = Sample { name: PathBuf::new(), data: vec![] };
+                for (key, value) in opcodes {
+                    match key {
+                        "name" => sample.name = PathBuf::from(value),
+                        "data" => sample.data = value.as_bytes().to_vec(),
+                        _ => {}
+                    }
+                }
+                self.sample.push(sample);
+            }
+        }
+    }
+}
+
+fn to_param_map(opcodes: Vec<(&str, &str)>) -> HashMap<String, String> {
+    opcodes
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn to_param_list(opcodes: Vec<(&str, &str)>) -> Vec<(String, String)> {
+    opcodes
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn effect_from_opcodes(opcodes: Vec<(&str, &str)>) -> Effect {
+    let mut effect = Effect::default();
+    for (key, value) in opcodes {
+        // Values that fail to parse are kept with the effect specific
+        // opcodes rather than silently dropped.
+        let applied = match key {
+            "type" => {
+                effect.effect_type = EffectType::from_name(value);
+                effect.effect_type.is_some()
+            }
+            "bus" => BusOption::from_name(value).map(|bus| effect.bus = bus).is_some(),
+            "param_offset" => value.parse().map(|offset| effect.param_offset = offset).is_ok(),
+            "effect1" => value.parse().map(|gain| effect.effect_one = gain).is_ok(),
+            "effect2" => value.parse().map(|gain| effect.effect_two = gain).is_ok(),
+            "effect3" => value.parse().map(|gain| effect.effect_three = gain).is_ok(),
+            "effect4" => value.parse().map(|gain| effect.effect_four = gain).is_ok(),
+            "dsp_order" => value.parse().map(|order| effect.dsp_order = order).is_ok(),
+            _ => false,
+        };
+        if !applied {
+            effect.parameters.insert(key.to_owned(), value.to_owned());
+        }
+    }
+    effect
+}
+
+/// A header whose opcodes are still being collected.
+struct OpenHeader<'a> {
+    header_type: HeaderType,
+    /// Byte range of the header tag in the source.
+    span: Range<usize>,
+    /// Keys are canonical, values are borrowed from the source unless
+    /// they were clamped.
+    opcodes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    /// Keys written with an alias, by canonical name.
+    spellings: HashMap<String, String>,
+    define_directives: Vec<(&'a str, &'a str)>,
+    include_directives: Vec<&'a str>,
+}
+
+impl<'a> OpenHeader<'a> {
+    /// Adds an opcode under its canonical name. Only the headers holding
+    /// region opcodes have their aliases resolved.
+    fn push_opcode(&mut self, key: &'a str, value: Cow<'a, str>) {
+        let holds_region_opcodes = matches!(
+            self.header_type,
+            HeaderType::Global | HeaderType::Master | HeaderType::Group | HeaderType::Region
+        );
+        let key = match canonical_name(key) {
+            Cow::Owned(canonical) if holds_region_opcodes => {
+                self.spellings.insert(canonical.clone(), key.to_owned());
+                Cow::Owned(canonical)
+            }
+            _ => Cow::Borrowed(key),
+        };
+        self.opcodes.push((key, value));
+    }
+}
+
+/// How opcode values are validated while parsing.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParseOptions {
+    pub range_policy: RangePolicy,
+    /// The octave note names in key opcodes use for middle C.
+    pub middle_c: MiddleC,
+    /// The player to parse for. Opcodes and headers it ignores are
+    /// skipped with a warning.
+    pub player: Option<PlayerProfile>,
+}
+
+/// The result of parsing in tolerant mode: whatever could be parsed,
+/// along with everything that had to be skipped or looked suspicious.
+#[derive(Clone, Debug)]
+pub struct ParseOutcome {
+    pub instrument: SfzInstrument<String>,
+    pub diagnostics: Vec<SfzError>,
+}
+
+impl ParseOutcome {
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &SfzError> {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &SfzError> {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Warning)
+    }
+}
+
+/// Parses a complete SFZ document, with its headers in any order,
+/// into an instrument. `#define` and `#include` directives found inside
+/// a `<control>` header are recorded on that header.
+pub fn parse_sfz(sfz_source: &str) -> Result<SfzInstrument<String>, SfzError> {
+    parse_sfz_with(sfz_source, &ParseOptions::default())
+}
+
+/// Parses a document like `parse_sfz` with the given options. Values of
+/// the wrong type are errors, values out of range are clamped or ignored.
+pub fn parse_sfz_with(sfz_source: &str, options: &ParseOptions) -> Result<SfzInstrument<String>, SfzError> {
+    parse_document(sfz_source, None, options)
+}
+
+/// Parses a document like `parse_sfz`, but skips malformed opcodes and
+/// headers instead of giving up, the way sfizz and ARIA load slightly
+/// broken libraries. The opcodes following an unknown header are ignored.
+pub fn parse_sfz_tolerant(sfz_source: &str) -> ParseOutcome {
+    parse_sfz_tolerant_with(sfz_source, &ParseOptions::default())
+}
+
+/// Parses a document like `parse_sfz_tolerant` with the given options.
+/// Opcodes with values of the wrong type are skipped, and values out of
+/// range are either clamped or reported as warnings.
+pub fn parse_sfz_tolerant_with(sfz_source: &str, options: &ParseOptions) -> ParseOutcome {
+    let mut diagnostics = vec![];
+    let instrument = parse_document(sfz_source, Some(&mut diagnostics), options)
+        .expect("tolerant parsing records errors instead of returning them");
+    ParseOutcome { instrument, diagnostics }
+}
+
+/// Parses a document, returning the first error unless `diagnostics` is
+/// given, in which case errors are recorded there and parsing carries on.
+fn parse_document(
+    sfz_source: &str,
+    mut diagnostics: Option<&mut Vec<SfzError>>,
+    options: &ParseOptions,
+) -> Result<SfzInstrument<String>, SfzError> {
+    let mut instrument = SfzInstrument::default();
+    let mut scope = HeaderScope::default();
+    let mut open_header: Option<OpenHeader> = None;
+    // Set after an unknown header, whose opcodes are skipped.
+    let mut ignoring_header = false;
+    let mut remaining = skip_white_space(sfz_source);
+
+    while !remaining.is_empty() {
+        if let Ok((rest, define)) = parse_define_line(remaining) {
+            if let Some(header) = open_header.as_mut() {
+                header.define_directives.push(define);
+            }
+            check_directive(sfz_source, remaining, rest, "#define", options, diagnostics.as_deref_mut());
+            remaining = rest;
+        } else if let Ok((rest, include)) = parse_include_line(remaining) {
+            if let Some(header) = open_header.as_mut() {
+                header.include_directives.push(include);
+            }
+            check_directive(sfz_source, remaining, rest, "#include", options, diagnostics.as_deref_mut());
+            remaining = rest;
+        } else if let Ok((rest, header_type)) = parse_header(remaining) {
+            if let Some(header) = open_header.take() {
+                instrument.close_header(&mut scope, header, options.middle_c);
+            }
+            let unsupported = options.player.filter(|player| !player.supports_header(header_type));
+            ignoring_header = unsupported.is_some();
+            let span = sfz_source.len() - remaining.len()..sfz_source.len() - rest.len();
+            if let Some(player) = unsupported {
+                if let Some(diagnostics) = diagnostics.as_deref_mut() {
+                    diagnostics.push(unsupported_header(sfz_source, span, header_type, player));
+                }
+            } else {
+                open_header = Some(OpenHeader {
+                    header_type,
+                    span,
+                    opcodes: vec![],
+                    spellings: HashMap::new(),
+                    define_directives: vec![],
+                    include_directives: vec![],
+                });
+            }
+            remaining = rest;
+        } else if let (true, Ok((rest, _))) = (ignoring_header, parse_key_value(remaining)) {
+            remaining = rest;
+        } else {
+            let header_type = open_header.as_ref().map(|header| header.header_type);
+            let parsed = match open_header.as_mut() {
+                Some(header) => parse_key_value(remaining).ok().map(|(rest, (key, value))| {
+                    if let Some(player) = options.player.filter(|player| !player.supports_opcode(key)) {
+                        return (rest, Some(unsupported_opcode(sfz_source, key, header.header_type, player)));
+                    }
+                    match validate_opcode(key, value, options.range_policy, options.middle_c) {
+                        Ok(value) => {
+                            header.push_opcode(key, value);
+                            if lookup(key).is_some_and(|info| !info.allows(header.header_type)) {
+                                return (rest, Some(misplaced_opcode(sfz_source, key, header.header_type)));
+                            }
+                        }
+                        Err(error) => {
+                            let diagnostic = value_error(sfz_source, key, value, header_type, &error);
+                            // Out of range values are kept, like players do.
+                            if diagnostic.severity == Severity::Warning {
+                                header.push_opcode(key, Cow::Borrowed(value));
+                            }
+                            return (rest, Some(diagnostic));
+                        }
+                    }
+                    (rest, None)
+                }),
+                None => None,
+            };
+            remaining = match parsed {
+                Some((rest, None)) => rest,
+                Some((rest, Some(diagnostic))) => match diagnostics.as_deref_mut() {
+                    Some(diagnostics) => {
+                        diagnostics.push(diagnostic);
+                        rest
+                    }
+                    None if diagnostic.severity == Severity::Error => return Err(diagnostic),
+                    None => rest,
+                },
+                None => {
+                    let error = diagnose(sfz_source, remaining, header_type);
+                    let Some(diagnostics) = diagnostics.as_deref_mut() else {
+                        return Err(error);
+                    };
+                    if error.kind == SfzErrorKind::UnknownHeader {
+                        if let Some(header) = open_header.take() {
+                            instrument.close_header(&mut scope, header, options.middle_c);
+                        }
+                        ignoring_header = true;
+                    }
+                    let skipped = error.span.end - error.span.start;
+                    diagnostics.push(error);
+                    skip_token(remaining, skipped)
+                }
+            };
+        }
+        remaining = skip_white_space(remaining);
+    }
+
+    if let Some(header) = open_header.take() {
+        instrument.close_header(&mut scope, header, options.middle_c);
+    }
+
+    Ok(instrument)
+}
+
+/// Preprocesses the file at `path` and parses it. Errors point into the
+/// file the offending text came from.
+pub fn load_sfz<L: FileLoader>(
+    preprocessor: &Preprocessor<L>,
+    path: impl AsRef<Path>,
+) -> Result<(SfzInstrument<String>, Preprocessed), SfzError> {
+    load_sfz_with(preprocessor, path, &ParseOptions::default())
+}
+
+/// Loads a file like `load_sfz`, parsing it with the given options.
+pub fn load_sfz_with<L: FileLoader>(
+    preprocessor: &Preprocessor<L>,
+    path: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> Result<(SfzInstrument<String>, Preprocessed), SfzError> {
+    let preprocessed = preprocessor.load(path)?;
+    match parse_sfz_with(&preprocessed.text, options) {
+        Ok(mut instrument) => {
+            forget_expanded_includes(&mut instrument);
+            Ok((instrument, preprocessed))
+        }
+        Err(error) => Err(error.relocate(&preprocessed.source_map)),
+    }
+}
+
+/// Preprocesses and parses the file at `path` in tolerant mode, skipping
+/// includes that can't be loaded as well as malformed text. Only fails
+/// when `path` itself can't be loaded.
+pub fn load_sfz_tolerant<L: FileLoader>(
+    preprocessor: &Preprocessor<L>,
+    path: impl AsRef<Path>,
+) -> Result<(ParseOutcome, Preprocessed), SfzError> {
+    load_sfz_tolerant_with(preprocessor, path, &ParseOptions::default())
+}
+
+/// Loads a file like `load_sfz_tolerant`, parsing it with the given options.
+pub fn load_sfz_tolerant_with<L: FileLoader>(
+    preprocessor: &Preprocessor<L>,
+    path: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> Result<(ParseOutcome, Preprocessed), SfzError> {
+    let preprocessed = preprocessor.load_tolerant(path)?;
+    let mut outcome = parse_sfz_tolerant_with(&preprocessed.text, options);
+    forget_expanded_includes(&mut outcome.instrument);
+    let parse_diagnostics = outcome
+        .diagnostics
+        .drain(..)
+        .map(|diagnostic| diagnostic.relocate(&preprocessed.source_map));
+    let mut diagnostics = preprocessed.diagnostics.clone();
+    diagnostics.extend(parse_diagnostics);
+    outcome.diagnostics = diagnostics;
+
+    Ok((outcome, preprocessed))
+}
+
+/// Clears the `#include` directives of an instrument parsed from
+/// preprocessed text. Their contents are already part of the instrument,
+/// so writing the directives back would load them twice.
+fn forget_expanded_includes(instrument: &mut SfzInstrument<String>) {
+    for control in &mut instrument.control {
+        control.include_directives.clear();
+    }
+}
+
+/// Skips at least `len` bytes, and at least one character, of `remaining`.
+fn skip_token(remaining: &str, len: usize) -> &str {
+    let len = remaining
+        .char_indices()
+        .map(|(index, c)| index + c.len_utf8())
+        .find(|end| *end >= len)
+        .unwrap_or(remaining.len());
+    &remaining[len..]
+}
+
+fn skip_white_space(sfz_source: &str) -> &str {
+    match white_space(sfz_source) {
+        Ok((remaining, _)) => remaining,
+        Err(_) => sfz_source,
+    }
+}
+
+/// Reports a value rejected by `validate_opcode`. Values out of range are
+/// only warned about, since players clamp them.
+fn value_error(
+    sfz_source: &str,
+    key: &str,
+    value: &str,
+    header_type: Option<HeaderType>,
+    error: &ValueError,
+) -> SfzError {
+    let start = value.as_ptr() as usize - sfz_source.as_ptr() as usize;
+    let span = start..start + value.len();
+    let diagnostic = match error {
+        ValueError::Invalid { .. } => SfzError::new(
+            SfzErrorKind::InvalidValue,
+            format!("invalid value `{value}` for opcode `{key}`: {error}"),
+            sfz_source,
+            span,
+        ),
+        ValueError::OutOfRange { .. } => SfzError::new(
+            SfzErrorKind::OutOfRange,
+            format!("value `{value}` for opcode `{key}` is out of range: {error}"),
+            sfz_source,
+            span,
+        )
+        .as_warning(),
+    };
+    diagnostic.with_header(header_type).with_opcode(key)
+}
+
+/// Warns about an opcode the registry doesn't allow in `header_type`.
+fn misplaced_opcode(sfz_source: &str, key: &str, header_type: HeaderType) -> SfzError {
+    let start = key.as_ptr() as usize - sfz_source.as_ptr() as usize;
+    SfzError::new(
+        SfzErrorKind::MisplacedOpcode,
+        format!("opcode `{key}` has no effect in a `<{}>` header", header_type.name()),
+        sfz_source,
+        start..start + key.len(),
+    )
+    .as_warning()
+    .with_header(Some(header_type))
+    .with_opcode(key)
+}
+
+/// Warns about an opcode `player` ignores.
+fn unsupported_opcode(sfz_source: &str, key: &str, header_type: HeaderType, player: PlayerProfile) -> SfzError {
+    let start = key.as_ptr() as usize - sfz_source.as_ptr() as usize;
+    SfzError::new(
+        SfzErrorKind::UnsupportedOpcode,
+        format!("opcode `{key}` is not supported by {}", player.name()),
+        sfz_source,
+        start..start + key.len(),
+    )
+    .as_warning()
+    .with_header(Some(header_type))
+    .with_opcode(key)
+}
+
+/// Warns about a header `player` ignores.
+fn unsupported_header(sfz_source: &str, span: Range<usize>, header_type: HeaderType, player: PlayerProfile) -> SfzError {
+    SfzError::new(
+        SfzErrorKind::UnsupportedHeader,
+        format!("header `<{}>` is not supported by {}, its opcodes are ignored", header_type.name(), player.name()),
+        sfz_source,
+        span,
+    )
+    .as_warning()
+    .with_header(Some(header_type))
+}
+
+/// Warns about the directive `name`, which runs from `remaining` up to
+/// `rest`, when the player in `options` doesn't expand it.
+fn check_directive(
+    sfz_source: &str,
+    remaining: &str,
+    rest: &str,
+    name: &str,
+    options: &ParseOptions,
+    diagnostics: Option<&mut Vec<SfzError>>,
+) {
+    let (Some(player), Some(diagnostics)) = (options.player, diagnostics) else {
+        return;
+    };
+    if player.supports_directive(name) {
+        return;
+    }
+    let start = sfz_source.len() - remaining.len();
+    let len = remaining[..remaining.len() - rest.len()].trim_end().len();
+    let warning = SfzError::new(
+        SfzErrorKind::UnsupportedDirective,
+        format!("directive `{name}` is not supported by {}", player.name()),
+        sfz_source,
+        start..start + len,
+    )
+    .as_warning()
+    .with_opcode(name);
+    diagnostics.push(warning);
+}
+
+/// Works out why nothing could be parsed at `remaining`, a suffix of `sfz_source`.
+fn diagnose(sfz_source: &str, remaining: &str, header_type: Option<HeaderType>) -> SfzError {
+    let start = sfz_source.len() - remaining.len();
+    let token_len = remaining.find(char::is_whitespace).unwrap_or(remaining.len());
+    let token = &remaining[..token_len];
+    let error = |kind, message: String, len: usize| {
+        SfzError::new(kind, message, sfz_source, start..start + len).with_header(header_type)
+    };
+
+    if token.starts_with('<') {
+        return match token.find('>') {
+            Some(end) => error(
+                SfzErrorKind::UnknownHeader,
+                format!("unknown header `{}`", &token[..=end]),
+                end + 1,
+            ),
+            None => error(SfzErrorKind::Syntax, format!("unterminated header `{token}`"), token_len),
+        };
+    }
+    if token.starts_with('#') {
+        return error(SfzErrorKind::Syntax, format!("malformed directive `{token}`"), token_len);
+    }
+
+    let key_len = token
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(token_len);
+    let key = &token[..key_len];
+    if key.is_empty() {
+        return error(
+            SfzErrorKind::Syntax,
+            format!("expected a header, opcode or directive, found `{token}`"),
+            token_len,
+        );
+    }
+    if !token[key_len..].starts_with('=') {
+        return error(SfzErrorKind::Syntax, format!("expected `=` after `{key}`"), token_len)
+            .with_opcode(key);
+    }
+    if header_type.is_none() {
+        return error(
+            SfzErrorKind::OpcodeOutsideHeader,
+            format!("opcode `{key}` appears before any header"),
+            token_len,
+        )
+        .with_opcode(key);
+    }
+    error(SfzErrorKind::MissingValue, format!("missing value for opcode `{key}`"), key_len + 1)
+        .with_opcode(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers_glued_to_values() {
+        let instrument = parse_sfz("<region> lokey=1<region>sample=a.wav").unwrap();
+        assert_eq!(instrument.region.len(), 2);
+        assert_eq!(instrument.region[0].low_key, 1);
+        assert_eq!(instrument.region[1].sample, "a.wav");
+    }
+
+    #[test]
+    fn reports_opcodes_without_a_key() {
+        let error = parse_sfz("<region> =5 sample=a.wav").unwrap_err();
+        assert_eq!(error.kind, SfzErrorKind::Syntax);
+        assert_eq!((error.line, error.column), (1, 10));
+
+        let outcome = parse_sfz_tolerant("<region> =5 sample=a.wav");
+        assert_eq!(outcome.instrument.region[0].sample, "a.wav");
+        assert!(outcome.instrument.region[0].parameters.keys().all(|key| !key.is_empty()));
+        assert!(outcome.has_errors());
+    }
+
+    #[test]
+    fn remembers_the_spelling_of_aliases() {
+        let instrument = parse_sfz("<group> gain_cc7=3 <region> loopmode=one_shot volume=1 sample=a.wav").unwrap();
+        let region = &instrument.region[0];
+        assert_eq!(region.parameters["loop_mode"], "one_shot");
+        assert!(!region.parameters.contains_key("loopmode"));
+        assert_eq!(region.spellings, HashMap::from([("loop_mode".to_owned(), "loopmode".to_owned())]));
+        assert_eq!(instrument.group[0].common_params["volume_oncc7"], "3");
+        assert_eq!(instrument.group[0].spellings["volume_oncc7"], "gain_cc7");
+    }
+
+    #[test]
+    fn scopes_regions_to_their_headers() {
+        let sfz_source = "<global> volume=-6 <group> lokey=10 <region> sample=a.wav <group> <region> sample=b.wav";
+        let instrument = parse_sfz(sfz_source).unwrap();
+        assert_eq!(instrument.region[0].scope.global, Some(0));
+        assert_eq!(instrument.region[0].scope.group, Some(0));
+        assert_eq!(instrument.region[1].scope.group, Some(1));
+        assert_eq!(instrument.group[1].regions, [1]);
+        assert_eq!(instrument.region[0].low_key, 10);
+        assert_eq!(instrument.region[1].low_key, 0);
+        assert_eq!(instrument.region[1].volume, -6.0);
+    }
+
+    #[test]
+    fn reads_key_names_with_the_middle_c_of_the_options() {
+        let options = ParseOptions { middle_c: MiddleC::C3, ..ParseOptions::default() };
+        let sfz_source = "<group> lokey=c3 <region> hikey=c4 pitch_keycenter=c3 sample=a.wav";
+        let instrument = parse_sfz_with(sfz_source, &options).unwrap();
+        let region = &instrument.region[0];
+        assert_eq!((region.low_key, region.high_key, region.pitch_keycenter), (60, 72, 60));
+    }
+
+    #[test]
+    fn warns_about_offsets_out_of_range() {
+        let outcome = parse_sfz_tolerant("<control> note_offset=200 octave_offset=-11 <region> sample=a.wav");
+        let kinds: Vec<_> = outcome.diagnostics.iter().map(|diagnostic| diagnostic.kind).collect();
+        assert_eq!(kinds, [SfzErrorKind::OutOfRange, SfzErrorKind::OutOfRange]);
+        assert_eq!(outcome.diagnostics[0].opcode.as_deref(), Some("note_offset"));
+        let control = &outcome.instrument.control[0];
+        assert_eq!((control.note_offset, control.octave_offset), (127, -10));
+    }
+}
@@ -0,0 +1,23 @@
+#![allow(dead_code)]
+pub mod comment;
+pub mod compatibility;
+pub mod control;
+pub mod cst;
+pub mod error;
+pub mod formatter;
+pub mod header_types;
+pub mod inheritance;
+pub mod instrument;
+pub mod lint;
+pub mod note;
+pub mod opcode;
+pub mod opcode_key;
+pub mod opcode_types;
+pub mod parser;
+pub mod preprocessor;
+pub mod profile;
+pub mod refinements;
+pub mod registry;
+pub mod region;
+pub mod validation;
+pub mod writer;
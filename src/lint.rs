@@ -0,0 +1,587 @@
+use crate::control::parse_define_directive;
+use crate::cst::{parse_syntax_tree, SyntaxNode};
+use crate::error::{SfzError, SfzErrorKind};
+use crate::header_types::HeaderType;
+use crate::inheritance::ResolvedRegion;
+use crate::instrument::{parse_sfz_tolerant_with, ParseOptions, SfzInstrument};
+use crate::note::{note_number, MiddleC};
+use crate::opcode_key::OpcodeKey;
+use crate::preprocessor::{FileLoader, MemoryLoader, Preprocessed, Preprocessor};
+use crate::profile::PlayerProfile;
+use crate::registry::canonical_name;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A check made by `lint`. Each can be turned off in `LintOptions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LintRule {
+    /// Regions sharing keys and velocities, which play together unless
+    /// round robins, random ranges or other conditions tell them apart.
+    OverlappingZones,
+    /// Regions whose key, random, channel or round robin conditions can
+    /// never all be met.
+    UnreachableRegion,
+    /// Samples that aren't where the player will look for them.
+    MissingSample,
+    /// `lovel` above `hivel`.
+    InvertedVelocity,
+    /// `loop_start` or `loop_end` past the end of a WAV sample.
+    LoopOutOfBounds,
+    /// An opcode set more than once in one header, counting aliases.
+    DuplicateOpcode,
+    /// A `#define` whose variable is never used.
+    UnusedDefine,
+    /// A `label_ccN` for a CC that no opcode responds to.
+    UnusedCcLabel,
+}
+
+pub const ALL_RULES: [LintRule; 8] = [
+    LintRule::OverlappingZones,
+    LintRule::UnreachableRegion,
+    LintRule::MissingSample,
+    LintRule::InvertedVelocity,
+    LintRule::LoopOutOfBounds,
+    LintRule::DuplicateOpcode,
+    LintRule::UnusedDefine,
+    LintRule::UnusedCcLabel,
+];
+
+impl LintRule {
+    pub fn from_name(name: &str) -> Option<LintRule> {
+        ALL_RULES.iter().copied().find(|rule| rule.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintRule::OverlappingZones => "overlapping_zones",
+            LintRule::UnreachableRegion => "unreachable_region",
+            LintRule::MissingSample => "missing_sample",
+            LintRule::InvertedVelocity => "inverted_velocity",
+            LintRule::LoopOutOfBounds => "loop_out_of_bounds",
+            LintRule::DuplicateOpcode => "duplicate_opcode",
+            LintRule::UnusedDefine => "unused_define",
+            LintRule::UnusedCcLabel => "unused_cc_label",
+        }
+    }
+}
+
+/// Which rules `lint` checks, and how sample paths are resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintOptions {
+    pub rules: HashSet<LintRule>,
+    /// The player whose `default_path` and path rules samples are looked
+    /// up with. ARIA's when not set.
+    pub player: Option<PlayerProfile>,
+}
+
+impl LintOptions {
+    fn player(&self) -> PlayerProfile {
+        self.player.unwrap_or(PlayerProfile::Aria)
+    }
+
+    /// Options to parse with, reading note names the way the player does.
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions { middle_c: self.player().middle_c(), ..ParseOptions::default() }
+    }
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        LintOptions { rules: ALL_RULES.into_iter().collect(), player: None }
+    }
+}
+
+impl LintOptions {
+    pub fn enable(&mut self, rule: LintRule) -> &mut Self {
+        self.rules.insert(rule);
+        self
+    }
+
+    pub fn disable(&mut self, rule: LintRule) -> &mut Self {
+        self.rules.remove(&rule);
+        self
+    }
+
+    pub fn is_enabled(&self, rule: LintRule) -> bool {
+        self.rules.contains(&rule)
+    }
+}
+
+/// Opcodes which make a region play only some of the time. Regions that
+/// differ in any of them aren't reported as overlapping.
+const CONDITIONS: [&str; 40] = [
+    "lochan", "hichan", "loccN", "hiccN", "lohdccN", "hihdccN", "lobend", "hibend", "loprog", "hiprog", "sw_lokey",
+    "sw_hikey", "sw_last", "sw_lolast", "sw_hilast", "sw_down", "sw_up", "sw_previous", "sw_vel", "lochanaft",
+    "hichanaft", "lopolyaft", "hipolyaft", "lobpm", "hibpm", "lotimer", "hitimer", "trigger", "start_loccN",
+    "start_hiccN", "start_lohdccN", "start_hihdccN", "sostenuto_sw", "sustain_sw", "sostenuto_lo", "sustain_lo",
+    "varN_mod", "varN_onccN", "lorand", "hirand",
+];
+
+/// CCs players respond to without being told: volume, pan, expression,
+/// sustain and sostenuto.
+const IMPLICIT_CCS: [u16; 5] = [7, 10, 11, 64, 66];
+
+/// Preprocesses and lints source that has already been read. Includes
+/// can't be followed and samples can't be looked for, so the rules that
+/// need the file system find nothing.
+pub fn lint_sfz(sfz_source: &str, options: &LintOptions) -> Vec<SfzError> {
+    let preprocessed = Preprocessor::new(MemoryLoader::new()).preprocess_tolerant("", sfz_source);
+    let outcome = parse_sfz_tolerant_with(&preprocessed.text, &options.parse_options());
+    lint(&preprocessed, &outcome.instrument, options)
+}
+
+/// Loads, parses and lints the file at `path`. Only the lint diagnostics
+/// are returned; parsing errors are left to the parser.
+pub fn load_and_lint<L: FileLoader>(
+    preprocessor: &Preprocessor<L>,
+    path: impl AsRef<Path>,
+    options: &LintOptions,
+) -> Result<Vec<SfzError>, SfzError> {
+    let preprocessed = preprocessor.load_tolerant(path)?;
+    let outcome = parse_sfz_tolerant_with(&preprocessed.text, &options.parse_options());
+    Ok(lint(&preprocessed, &outcome.instrument, options))
+}
+
+/// Checks an instrument parsed from `preprocessed` against the enabled
+/// rules. Every diagnostic is a warning pointing into the file it's about.
+pub fn lint(preprocessed: &Preprocessed, instrument: &SfzInstrument<String>, options: &LintOptions) -> Vec<SfzError> {
+    let linter = Linter::new(preprocessed, instrument, options);
+    let mut diagnostics = vec![];
+    for (index, region) in linter.regions.iter().enumerate() {
+        let mut check = |rule, message: Option<String>| {
+            if let Some(message) = message.filter(|_| options.is_enabled(rule)) {
+                diagnostics.push(linter.warning(rule, message, linter.region_span(index)));
+            }
+        };
+        check(LintRule::InvertedVelocity, inverted_velocity(region));
+        check(LintRule::UnreachableRegion, unreachable(region, options.player().middle_c()));
+        check(LintRule::OverlappingZones, linter.overlap(index));
+        check(LintRule::MissingSample, linter.missing_sample(index));
+        check(LintRule::LoopOutOfBounds, linter.loop_out_of_bounds(index));
+    }
+    if options.is_enabled(LintRule::DuplicateOpcode) {
+        diagnostics.extend(linter.duplicate_opcodes());
+    }
+    if options.is_enabled(LintRule::UnusedCcLabel) {
+        diagnostics.extend(linter.unused_cc_labels());
+    }
+    let mut diagnostics: Vec<SfzError> =
+        diagnostics.into_iter().map(|diagnostic| diagnostic.relocate(&preprocessed.source_map)).collect();
+    if options.is_enabled(LintRule::UnusedDefine) {
+        diagnostics.extend(unused_defines(preprocessed));
+    }
+    diagnostics
+}
+
+struct Linter<'a> {
+    preprocessed: &'a Preprocessed,
+    instrument: &'a SfzInstrument<String>,
+    options: &'a LintOptions,
+    regions: Vec<ResolvedRegion>,
+    /// Every node of the preprocessed text, with its byte range.
+    nodes: Vec<(SyntaxNode, Range<usize>)>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(preprocessed: &'a Preprocessed, instrument: &'a SfzInstrument<String>, options: &'a LintOptions) -> Self {
+        let mut offset = 0;
+        let nodes: Vec<(SyntaxNode, Range<usize>)> = parse_syntax_tree(&preprocessed.text)
+            .nodes
+            .into_iter()
+            .map(|node| {
+                let len = node.to_string().len();
+                offset += len;
+                (node, offset - len..offset)
+            })
+            .collect();
+        Linter { preprocessed, instrument, options, regions: instrument.resolve_regions(), nodes }
+    }
+
+    fn warning(&self, rule: LintRule, message: String, span: Range<usize>) -> SfzError {
+        SfzError::new(SfzErrorKind::Lint(rule), message, &self.preprocessed.text, span).as_warning()
+    }
+
+    fn region_span(&self, region: usize) -> Range<usize> {
+        self.instrument.region[region].span.clone()
+    }
+
+    fn region_line(&self, region: usize) -> usize {
+        self.warning(LintRule::OverlappingZones, String::new(), self.region_span(region))
+            .relocate(&self.preprocessed.source_map)
+            .line
+    }
+
+    /// Reports the first earlier region `index` plays together with.
+    fn overlap(&self, index: usize) -> Option<String> {
+        let region = &self.instrument.region[index];
+        let resolved = &self.regions[index];
+        let middle_c = self.options.player().middle_c();
+        if !playable(resolved, middle_c) {
+            return None;
+        }
+        let earlier = (0..index).find(|other| {
+            let other_region = &self.instrument.region[*other];
+            ranges_overlap((region.low_key, region.high_key), (other_region.low_key, other_region.high_key))
+                && ranges_overlap(
+                    (region.low_velocity, region.high_velocity),
+                    (other_region.low_velocity, other_region.high_velocity),
+                )
+                && playable(&self.regions[*other], middle_c)
+                && !separated(resolved, &self.regions[*other])
+        })?;
+        Some(format!(
+            "region overlaps the region on line {} without round robin or random separation",
+            self.region_line(earlier),
+        ))
+    }
+
+    /// Where the player looks for the region's sample, unless it's one
+    /// of the generated `*sine` style samples.
+    fn sample_path(&self, index: usize) -> Option<PathBuf> {
+        let region = &self.instrument.region[index];
+        let root = self.preprocessed.source_map.files.first()?.path.parent()?;
+        if region.sample.is_empty() || region.sample.starts_with('*') || root.as_os_str().is_empty() {
+            return None;
+        }
+        Some(root.join(self.instrument.sample_path(index, self.options.player())?))
+    }
+
+    fn missing_sample(&self, index: usize) -> Option<String> {
+        let path = self.sample_path(index)?;
+        (!path.exists())
+            .then(|| format!("sample `{}` not found at {}", self.instrument.region[index].sample, path.display()))
+    }
+
+    fn loop_out_of_bounds(&self, index: usize) -> Option<String> {
+        let frames = wav_frames(&self.sample_path(index)?)?;
+        ["loop_start", "loop_end"].into_iter().find_map(|key| {
+            let point: u64 = self.regions[index].get(key)?.parse().ok()?;
+            (point > frames)
+                .then(|| format!("`{key}={point}` is past the end of the sample, which has {frames} frames"))
+        })
+    }
+
+    /// The index of the header each node belongs to.
+    fn headers(&self) -> Vec<Option<usize>> {
+        let mut header = None;
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, (node, _))| {
+                if let SyntaxNode::Header(_) = node {
+                    header = Some(index);
+                }
+                header
+            })
+            .collect()
+    }
+
+    fn duplicate_opcodes(&self) -> Vec<SfzError> {
+        let mut seen: HashMap<(Option<usize>, String), String> = HashMap::new();
+        let mut diagnostics = vec![];
+        for ((node, span), header) in self.nodes.iter().zip(self.headers()) {
+            let SyntaxNode::Opcode { key, .. } = node else {
+                continue;
+            };
+            let key_span = span.start..span.start + key.len();
+            match seen.get(&(header, canonical_name(key).into_owned())) {
+                Some(first) if first == key => {
+                    let message = format!("`{key}` is set more than once in this header, the last value is used");
+                    diagnostics.push(self.warning(LintRule::DuplicateOpcode, message, key_span));
+                }
+                Some(first) => {
+                    let message = format!("`{key}` sets `{first}` again in this header, the last value is used");
+                    diagnostics.push(self.warning(LintRule::DuplicateOpcode, message, key_span));
+                }
+                None => {
+                    seen.insert((header, canonical_name(key).into_owned()), key.clone());
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// The CCs opcodes respond to, either by name, as in `cutoff_oncc74`,
+    /// or by value, as in `sustain_cc=64`.
+    fn used_ccs(&self) -> HashSet<u16> {
+        let instrument = self.instrument;
+        let opcodes = instrument
+            .global
+            .iter()
+            .flat_map(|global| &global.common_params)
+            .chain(
+                instrument
+                    .master
+                    .iter()
+                    .flatten()
+                    .flat_map(|master| master.op_codes.iter().map(|(key, value)| (key, value))),
+            )
+            .chain(instrument.group.iter().flat_map(|group| &group.common_params))
+            .chain(instrument.region.iter().flat_map(|region| &region.parameters))
+            .chain(instrument.effect.iter().flat_map(|effect| &effect.parameters));
+        let mut used: HashSet<u16> = IMPLICIT_CCS.into_iter().collect();
+        for (key, value) in opcodes {
+            if key.ends_with("_cc") {
+                used.extend(value.parse::<u16>().ok());
+            } else {
+                used.extend(OpcodeKey::parse(key).and_then(|key| key.cc()));
+            }
+        }
+        used
+    }
+
+    fn unused_cc_labels(&self) -> Vec<SfzError> {
+        let used = self.used_ccs();
+        let mut diagnostics = vec![];
+        for ((node, span), header) in self.nodes.iter().zip(self.headers()) {
+            let SyntaxNode::Opcode { key, .. } = node else {
+                continue;
+            };
+            let in_control =
+                header.is_some_and(|header| self.nodes[header].0.header_type() == Some(HeaderType::Control));
+            let Some(cc) =
+                OpcodeKey::parse(key).filter(|parsed| parsed.base == "label_ccN").and_then(|parsed| parsed.cc())
+            else {
+                continue;
+            };
+            if in_control && !used.contains(&cc) {
+                let message = format!("CC {cc} is labeled, but no opcode responds to it");
+                diagnostics.push(self.warning(LintRule::UnusedCcLabel, message, span.start..span.start + key.len()));
+            }
+        }
+        diagnostics
+    }
+}
+
+fn ranges_overlap(a: (u8, u8), b: (u8, u8)) -> bool {
+    a.0 <= b.1 && b.0 <= a.1
+}
+
+/// Whether two regions that share keys and velocities still never play together.
+fn separated(a: &ResolvedRegion, b: &ResolvedRegion) -> bool {
+    let number = |region: &ResolvedRegion, key: &str, default: f64| {
+        region.get(key).and_then(|value| value.parse().ok()).unwrap_or(default)
+    };
+    let round_robin = |region: &ResolvedRegion| number(region, "seq_length", 1.0) > 1.0;
+    if (round_robin(a) || round_robin(b)) && number(a, "seq_position", 1.0) != number(b, "seq_position", 1.0) {
+        return true;
+    }
+    if number(a, "hirand", 1.0) <= number(b, "lorand", 0.0) || number(b, "hirand", 1.0) <= number(a, "lorand", 0.0) {
+        return true;
+    }
+    let conditions = |region: &'_ ResolvedRegion| -> Vec<(String, String)> {
+        let mut conditions: Vec<(String, String)> = region
+            .opcodes
+            .iter()
+            .filter(|(key, _)| OpcodeKey::parse(key).is_some_and(|parsed| CONDITIONS.contains(&parsed.base.as_str())))
+            .map(|(key, resolved)| (key.clone(), resolved.value.clone()))
+            .collect();
+        conditions.sort();
+        conditions
+    };
+    conditions(a) != conditions(b)
+}
+
+fn playable(region: &ResolvedRegion, middle_c: MiddleC) -> bool {
+    inverted_velocity(region).is_none() && unreachable(region, middle_c).is_none()
+}
+
+fn inverted_velocity(region: &ResolvedRegion) -> Option<String> {
+    let lovel: u8 = region.get("lovel")?.parse().ok()?;
+    let hivel: u8 = region.get("hivel").and_then(|hivel| hivel.parse().ok()).unwrap_or(127);
+    (lovel > hivel).then(|| format!("`lovel={lovel}` is above `hivel={hivel}`, so no velocity plays the region"))
+}
+
+/// Why a region can never play, leaving inverted velocities to their own
+/// rule. Keys written as note names are read with `middle_c`.
+fn unreachable(region: &ResolvedRegion, middle_c: MiddleC) -> Option<String> {
+    let number =
+        |key: &str, default: f64| region.get(key).and_then(|value| value.parse::<f64>().ok()).unwrap_or(default);
+    let note = |key: &str| region.get(key).and_then(|value| note_number(value, middle_c));
+    let key = note("key");
+    let (lokey, hikey) = (note("lokey").or(key).unwrap_or(0), note("hikey").or(key).unwrap_or(127));
+    if lokey > hikey {
+        return Some(format!("`lokey` {lokey} is above `hikey` {hikey}, so no key plays the region"));
+    }
+    if number("lorand", 0.0) >= number("hirand", 1.0) {
+        return Some("`lorand` isn't below `hirand`, so no random value plays the region".to_owned());
+    }
+    if number("lochan", 1.0) > number("hichan", 16.0) {
+        return Some("`lochan` is above `hichan`, so no channel plays the region".to_owned());
+    }
+    if number("seq_position", 1.0) > number("seq_length", 1.0) {
+        return Some("`seq_position` is past `seq_length`, so the round robin never reaches the region".to_owned());
+    }
+    None
+}
+
+/// Warns about each `#define` whose variable was never substituted.
+fn unused_defines(preprocessed: &Preprocessed) -> Vec<SfzError> {
+    preprocessed
+        .defines
+        .iter()
+        .filter(|define| define.uses == 0)
+        .map(|define| {
+            let file = &preprocessed.source_map.files[define.location.file];
+            let start = define.location.offset;
+            let end = parse_define_directive(&file.text[start..])
+                .map_or(file.text.len(), |(rest, _)| file.text.len() - rest.len());
+            let message = format!("`${}` is defined, but never used", define.name);
+            SfzError::new(SfzErrorKind::Lint(LintRule::UnusedDefine), message, &file.text, start..end)
+                .as_warning()
+                .in_file(&file.path)
+        })
+        .collect()
+}
+
+/// The number of frames in a WAV file, read from its chunk headers.
+fn wav_frames(path: &Path) -> Option<u64> {
+    let mut file = File::open(path).ok()?;
+    let mut riff = [0; 12];
+    file.read_exact(&mut riff).ok()?;
+    if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+        return None;
+    }
+    let mut block_align = None;
+    loop {
+        let mut chunk = [0; 8];
+        file.read_exact(&mut chunk).ok()?;
+        let size = u32::from_le_bytes(chunk[4..].try_into().ok()?);
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut format = [0; 16];
+                file.read_exact(&mut format).ok()?;
+                block_align = Some(u16::from_le_bytes(format[12..14].try_into().ok()?));
+                file.seek(SeekFrom::Current(i64::from(size) - 16 + i64::from(size % 2))).ok()?;
+            }
+            b"data" => return Some(u64::from(size) / u64::from(block_align.filter(|align| *align > 0)?)),
+            _ => {
+                file.seek(SeekFrom::Current(i64::from(size) + i64::from(size % 2))).ok()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessor::FsLoader;
+    use std::fs;
+
+    fn rules(diagnostics: &[SfzError]) -> Vec<(LintRule, usize)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| match diagnostic.kind {
+                SfzErrorKind::Lint(rule) => (rule, diagnostic.line),
+                kind => panic!("`{kind:?}` isn't a lint"),
+            })
+            .collect()
+    }
+
+    fn lint_str(sfz_source: &str) -> Vec<(LintRule, usize)> {
+        rules(&lint_sfz(sfz_source, &LintOptions::default()))
+    }
+
+    /// Writes `files` to a directory of their own and lints the first.
+    fn lint_files(name: &str, files: &[(&str, &[u8])]) -> Vec<(LintRule, usize)> {
+        let dir = std::env::temp_dir().join(format!("soundry-lint-{name}"));
+        fs::create_dir_all(&dir).unwrap();
+        for (path, contents) in files {
+            fs::write(dir.join(path), contents).unwrap();
+        }
+        rules(&load_and_lint(&Preprocessor::new(FsLoader), dir.join(files[0].0), &LintOptions::default()).unwrap())
+    }
+
+    fn wav(frames: u32) -> Vec<u8> {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0\x44\xac\0\0\x88\x58\x01\0\x02\0\x10\0data".to_vec();
+        wav.extend((frames * 2).to_le_bytes());
+        wav.extend(vec![0; frames as usize * 2]);
+        wav
+    }
+
+    #[test]
+    fn finds_overlapping_zones() {
+        let diagnostics = lint_sfz("<region> sample=a.wav\n<region> sample=b.wav lokey=60\n", &LintOptions::default());
+        assert_eq!(rules(&diagnostics), [(LintRule::OverlappingZones, 2)]);
+        assert!(diagnostics[0].message.contains("line 1"));
+    }
+
+    #[test]
+    fn lets_round_robins_and_conditions_share_zones() {
+        assert_eq!(lint_str("<group> seq_length=2\n<region> seq_position=1\n<region> seq_position=2\n"), []);
+        assert_eq!(lint_str("<region> lorand=0 hirand=0.5\n<region> lorand=0.5 hirand=1\n"), []);
+        assert_eq!(lint_str("<region> sw_last=36\n<region> sw_last=37\n"), []);
+    }
+
+    #[test]
+    fn finds_unreachable_regions() {
+        assert_eq!(lint_str("<region> lokey=70 hikey=60\n"), [(LintRule::UnreachableRegion, 1)]);
+        assert_eq!(lint_str("<region> seq_length=2 seq_position=3\n"), [(LintRule::UnreachableRegion, 1)]);
+    }
+
+    #[test]
+    fn reads_keys_written_as_note_names() {
+        assert_eq!(lint_str("<region> lokey=c5 hikey=b4\n"), [(LintRule::UnreachableRegion, 1)]);
+        assert_eq!(lint_str("<region> lokey=c4 hikey=c#4\n"), []);
+        assert_eq!(lint_str("<region> lokey=c4 hikey=60\n<region> lokey=61 hikey=c6\n"), []);
+    }
+
+    #[test]
+    fn reads_note_names_with_the_players_middle_c() {
+        let options = LintOptions { player: Some(PlayerProfile::Cakewalk), ..LintOptions::default() };
+        let sfz_source = "<region> lokey=c4 hikey=c4\n<region> key=72\n";
+        assert!(lint_sfz(sfz_source, &LintOptions::default()).is_empty());
+        let diagnostics = lint_sfz(sfz_source, &options);
+        assert_eq!((diagnostics[0].kind, diagnostics[0].line), (SfzErrorKind::Lint(LintRule::OverlappingZones), 2));
+    }
+
+    #[test]
+    fn finds_inverted_velocities() {
+        assert_eq!(lint_str("<group> hivel=60\n\n<region> lovel=100\n"), [(LintRule::InvertedVelocity, 3)]);
+    }
+
+    #[test]
+    fn finds_duplicate_opcodes_counting_aliases() {
+        assert_eq!(lint_str("<region> volume=1\n volume=2\n"), [(LintRule::DuplicateOpcode, 2)]);
+        assert_eq!(lint_str("<region> loop_mode=one_shot\n loopmode=no_loop\n"), [(LintRule::DuplicateOpcode, 2)]);
+        assert_eq!(
+            lint_str("<region> volume=1 <region> volume=2 lokey=60 hikey=59"),
+            [(LintRule::UnreachableRegion, 1)]
+        );
+    }
+
+    #[test]
+    fn finds_unused_defines() {
+        assert_eq!(lint_str("#define $A 1\n#define $B 2\n<region> lokey=$A\n"), [(LintRule::UnusedDefine, 2)]);
+    }
+
+    #[test]
+    fn finds_unused_cc_labels() {
+        let sfz_source =
+            "<control> label_cc7=Volume label_cc20=Attack label_cc21=Release\n<region> ampeg_attack_oncc20=1\n";
+        assert_eq!(lint_str(sfz_source), [(LintRule::UnusedCcLabel, 1)]);
+    }
+
+    #[test]
+    fn finds_missing_samples_and_loops_past_their_end() {
+        let sfz_source = b"<region> key=0 sample=a.wav loop_end=50\n<region> key=1 sample=b.wav loop_end=200\n<region> key=2 sample=c.wav\n";
+        let found = lint_files("samples", &[("a.sfz", sfz_source), ("a.wav", &wav(100)), ("b.wav", &wav(100))]);
+        assert_eq!(found, [(LintRule::LoopOutOfBounds, 2), (LintRule::MissingSample, 3)]);
+    }
+
+    #[test]
+    fn skips_disabled_rules() {
+        let mut options = LintOptions::default();
+        options.disable(LintRule::InvertedVelocity);
+        assert!(lint_sfz("<region> lovel=100 hivel=60\n", &options).is_empty());
+    }
+
+    #[test]
+    fn points_at_regions_after_text_that_didnt_parse() {
+        let sfz_source = "<region> key=0\n\"bad<region> volume=1\n<region> key=2 lovel=100 hivel=60\n";
+        assert_eq!(lint_str(sfz_source), [(LintRule::InvertedVelocity, 3)]);
+    }
+}
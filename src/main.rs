@@ -1,92 +1,232 @@
-#![allow(dead_code)]
-use control::{parse_control, parse_include_line};
-use serde::{Deserialize, Serialize};
-use std::boxed::Box;
-use std::error::Error;
+use soundry::compatibility::{load_compatibility, CompatibilityReport};
+use soundry::error::SfzError;
+use soundry::formatter::{format_sfz, FormatOptions, NoteStyle};
+use soundry::instrument::{load_sfz_tolerant_with, load_sfz_with, ParseOptions};
+use soundry::lint::{load_and_lint, LintOptions, LintRule};
+use soundry::note::MiddleC;
+use soundry::preprocessor::{FsLoader, Preprocessor};
+use soundry::profile::{PlayerProfile, ALL_PLAYERS};
+use soundry::registry::opcodes;
+use soundry::writer::WriteStyle;
+use std::env;
+use std::fs;
+use std::process::ExitCode;
 
-use crate::header_types::{Control, Effect, Global, Group, Master};
-use crate::opcode_types::{BusOption, EffectType};
-use crate::region::{parse_region, Region};
-mod control;
-mod header_types;
-mod opcode_types;
-mod parser;
-mod refinements;
-mod region;
-// Copy
-// Eq
-// PartialEq
-// Ord
-// PartialOrd
-// Hash
-// Display
-// Default
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "opcodes") {
+        print_opcodes();
+        return ExitCode::SUCCESS;
+    }
+    if args.first().is_some_and(|arg| arg == "fmt") {
+        return format_files(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "lint") {
+        return lint_file(&args[1..]);
+    }
+    let compatibility = args.first().is_some_and(|arg| arg == "compat");
+    let tolerant = args.iter().any(|arg| arg == "--tolerant");
+    let player = args.iter().find_map(|arg| arg.strip_prefix("--player="));
+    let Some(path) = args.iter().skip(usize::from(compatibility)).find(|arg| !arg.starts_with("--")) else {
+        eprintln!(
+            "usage: soundry [--tolerant] [--player=<player>] <file.sfz>\n       \
+             soundry compat [--player=<player>] <file.sfz>\n       \
+             soundry lint [--disable=<rule>]... [--player=<player>] <file.sfz>\n       \
+             soundry fmt [--check] [--compact] [--note-names] [--middle-c=c3|c4] <file.sfz>...\n       \
+             soundry opcodes"
+        );
+        return ExitCode::FAILURE;
+    };
+    let player = match player.map(|name| (name, PlayerProfile::from_name(name))) {
+        Some((_, Some(player))) => Some(player),
+        Some((name, None)) => {
+            eprintln!("unknown player `{name}`, expected aria, sfizz, cakewalk, linuxsampler or bassmidi");
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+    let options = player.map_or_else(ParseOptions::default, |player| player.parse_options());
+    let preprocessor = Preprocessor::new(FsLoader);
 
-#[derive(Clone, Debug)]
-struct SfzInstrument<T> {
-    global: Vec<Global<T>>,
-    control: Vec<Control>,
-    group: Vec<Group<T>>,
-    master: Option<Vec<Master<T>>>,
-    region: Vec<Region>,
-    effect: Vec<Effect>,
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-
-    let control_header = r#"
+    if compatibility {
+        let players = player.map_or(ALL_PLAYERS.to_vec(), |player| vec![player]);
+        return match load_compatibility(&preprocessor, path, &players) {
+            Ok(reports) => print_compatibility(&reports),
+            Err(error) => {
+                report(&error);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
+    if tolerant {
+        return match load_sfz_tolerant_with(&preprocessor, path, &options) {
+            Ok((outcome, _)) => {
+                outcome.diagnostics.iter().for_each(report);
+                println!("{:#?}", outcome.instrument);
+                if outcome.has_errors() { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+            }
+            Err(error) => {
+                report(&error);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-    <control>
-    default_path=Samples\
-
-    #define $EXT wav
-    #define $KICKKEY 36
-    #define $SNAREKEY 38
-    #define $HATKEY 42
-    #include "data\control.sfz"
-    #include "data\multiout.sfz"
-    #include "data\global.sfz"
-    #include "data\kick.sfz"
-    #include "data\snare.sfz"
-    #include "data\tom1.sfz"
-    #include "data\tom2.sfz"
-    #include "data\hihat.sfz"
-    #include "data\ride.sfz"
-    #include "data\crash.sfz"
-    label_cc30=Bass vol
-    label_cc31=Bass pan
-    label_cc32=Tune
-    label_cc33=Mute
-    set_cc40=127
-    set_cc100=30"#;
+    match load_sfz_with(&preprocessor, path, &options) {
+        Ok((instrument, _)) => {
+            println!("{instrument:#?}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            report(&error);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let cmplx_control_header = r#"#include \"data\global.sfz\"
-#include \"data\kick.sfz\"
-#include \"data\snare.sfz\"
-#include \"data\tom1.sfz\"
-#include \"data\tom2.sfz\"
-#include \"data\hihat.sfz\"
-#include \"data\ride.sfz\"
-#include \"data\crash.sfz\"#;
+/// Prints an error, with a snippet of the offending line when its file can be read.
+fn report(error: &SfzError) {
+    match error.file.as_ref().and_then(|file| fs::read_to_string(file).ok()) {
+        Some(sfz_source) => eprint!("{}", error.render(&sfz_source)),
+        None => eprintln!("{error}"),
+    }
+}
 
-    let simple_control_header = "<control>
-    default_path=Samples/
-    #define $EXT flac
-    #define $EXTTWO wav
-    #include \"data/control.sfz\"";
+/// Prints what each player would ignore, failing if any player ignores anything.
+fn print_compatibility(reports: &[CompatibilityReport]) -> ExitCode {
+    for report in reports {
+        if report.is_compatible() {
+            println!("{}: compatible", report.player.name());
+            continue;
+        }
+        println!(
+            "{}: {} unsupported opcodes, {} headers, {} directives",
+            report.player.name(),
+            report.opcodes().count(),
+            report.headers().count(),
+            report.directives().count(),
+        );
+        for issue in &report.issues {
+            let location = match &issue.file {
+                Some(file) => format!("{}:{}", file.display(), issue.line),
+                None => issue.line.to_string(),
+            };
+            println!("  {location}: {}", issue.message);
+        }
+    }
+    if reports.iter().all(CompatibilityReport::is_compatible) { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
 
-    // let (remaining, output) = parse_identifier(simple_control_header)?;
-    // println!("Remaining: {remaining}, Output: {:?}", output);
-    // let (remaining, output) = parse_default_path(remaining)?;
-    // println!("Remaining: {remaining}, Output: {:?}", output);
+/// Lints a file with every rule but the disabled ones, failing if anything is found.
+fn lint_file(args: &[String]) -> ExitCode {
+    let mut options = LintOptions::default();
+    for name in args.iter().filter_map(|arg| arg.strip_prefix("--disable=")) {
+        let Some(rule) = LintRule::from_name(name) else {
+            eprintln!("unknown lint rule `{name}`");
+            return ExitCode::FAILURE;
+        };
+        options.disable(rule);
+    }
+    if let Some(name) = args.iter().find_map(|arg| arg.strip_prefix("--player=")) {
+        let Some(player) = PlayerProfile::from_name(name) else {
+            eprintln!("unknown player `{name}`, expected aria, sfizz, cakewalk, linuxsampler or bassmidi");
+            return ExitCode::FAILURE;
+        };
+        options.player = Some(player);
+    }
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: soundry lint [--disable=<rule>]... [--player=<player>] <file.sfz>");
+        return ExitCode::FAILURE;
+    };
+    match load_and_lint(&Preprocessor::new(FsLoader), path, &options) {
+        Ok(diagnostics) => {
+            diagnostics.iter().for_each(report);
+            if diagnostics.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+        }
+        Err(error) => {
+            report(&error);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // let (remaining, output) = parse_define_value(remaining)?;
-    // println!("Remaining: {remaining}, Output: {:?}", output);
-    let (remaining, control_header) = parse_control(control_header)?;
-    // let (remaining, output) = parse_include_line(cmplx_control_header)?;
+const FORMAT_USAGE: &str =
+    "usage: soundry fmt [--check] [--compact] [--note-names] [--middle-c=c3|c4] <file.sfz>...";
 
-    // println!("Remaining: {remaining}, Control Header: {:?}", control_header);
+/// Formats each file in place or, with `--check`, lists the files that
+/// aren't formatted and fails if there are any. Unknown flags are
+/// rejected rather than ignored, so a misspelled `--check` can't rewrite
+/// the files.
+fn format_files(args: &[String]) -> ExitCode {
+    let mut check = false;
+    let mut options = FormatOptions::default();
+    for arg in args.iter().filter(|arg| arg.starts_with("--")) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--compact" => options.style = WriteStyle::Compact,
+            "--note-names" => options.notes = NoteStyle::Names,
+            "--middle-c=c3" => options.middle_c = MiddleC::C3,
+            "--middle-c=c4" => options.middle_c = MiddleC::C4,
+            _ => {
+                eprintln!("unknown option `{arg}`");
+                eprintln!("{FORMAT_USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    if paths.is_empty() {
+        eprintln!("{FORMAT_USAGE}");
+        return ExitCode::FAILURE;
+    }
+    let mut succeeded = true;
+    for path in paths {
+        let sfz_source = match fs::read_to_string(path) {
+            Ok(sfz_source) => sfz_source,
+            Err(error) => {
+                eprintln!("could not load {path}: {error}");
+                succeeded = false;
+                continue;
+            }
+        };
+        let formatted = match format_sfz(&sfz_source, &options) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                report(&error.in_file(path));
+                succeeded = false;
+                continue;
+            }
+        };
+        if formatted == sfz_source {
+            continue;
+        }
+        if check {
+            println!("{path} is not formatted");
+            succeeded = false;
+        } else if let Err(error) = fs::write(path, formatted) {
+            eprintln!("could not write {path}: {error}");
+            succeeded = false;
+        }
+    }
+    if succeeded { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
 
-    Ok(())
+/// Prints the opcode registry as a Markdown table.
+fn print_opcodes() {
+    println!("| Opcode | Version | Type | Default | Range | Unit | Headers |");
+    println!("|---|---|---|---|---|---|---|");
+    for info in opcodes() {
+        let headers: Vec<&str> = info.headers.iter().map(|header| header.name()).collect();
+        println!(
+            "| `{}` | {} | {} | {} | {} | {} | {} |",
+            info.name,
+            info.version.name(),
+            info.value_type.name(),
+            info.default.unwrap_or("-"),
+            info.range.map_or("-", |range| range.name()),
+            info.unit.unwrap_or("-"),
+            headers.join(", "),
+        );
+    }
 }
@@ -0,0 +1,140 @@
+use nom::{
+    character::complete::{i32 as octave, one_of},
+    combinator::opt,
+    IResult,
+};
+
+/// Which octave number middle C, MIDI note 60, is written with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MiddleC {
+    /// Note 60 is `c3`, as in Cakewalk and many DAWs.
+    C3,
+    /// Note 60 is `c4`, as in the SFZ spec, ARIA and sfizz.
+    #[default]
+    C4,
+}
+
+impl MiddleC {
+    /// The octave number of middle C.
+    pub fn octave(&self) -> i32 {
+        match self {
+            MiddleC::C3 => 3,
+            MiddleC::C4 => 4,
+        }
+    }
+}
+
+/// Parses a note name such as `c4`, `f#3` or `eb-1` to its MIDI note number.
+/// Names are case-insensitive, take one `#` or `b`, and fail to parse
+/// outside of the MIDI note range.
+pub fn parse_note_name(sfz_source: &str, middle_c: MiddleC) -> IResult<&str, u8> {
+    let (remaining, note) = parse_any_note_name(sfz_source, middle_c)?;
+    match u8::try_from(note) {
+        Ok(note) if note <= 127 => Ok((remaining, note)),
+        _ => Err(nom::Err::Error(nom::error::Error::new(sfz_source, nom::error::ErrorKind::Verify))),
+    }
+}
+
+/// Parses a note name like `parse_note_name`, to the number it would
+/// have even outside of the MIDI note range, as `a9` is 129.
+pub fn parse_any_note_name(sfz_source: &str, middle_c: MiddleC) -> IResult<&str, i64> {
+    let (remaining, letter) = one_of("abcdefgABCDEFG")(sfz_source)?;
+    let (remaining, accidental) = opt(one_of("#b"))(remaining)?;
+    let (remaining, octave) = octave(remaining)?;
+
+    let semitone = match letter.to_ascii_lowercase() {
+        'c' => 0,
+        'd' => 2,
+        'e' => 4,
+        'f' => 5,
+        'g' => 7,
+        'a' => 9,
+        _ => 11,
+    } + match accidental {
+        Some('#') => 1,
+        Some(_) => -1,
+        None => 0,
+    };
+    Ok((remaining, (i64::from(octave) - i64::from(middle_c.octave()) + 5) * 12 + semitone))
+}
+
+/// Reads a key opcode's value, which is either a MIDI note number or a note name.
+pub fn note_number(value: &str, middle_c: MiddleC) -> Option<u8> {
+    if let Ok(note) = value.parse::<u8>() {
+        return (note <= 127).then_some(note);
+    }
+    match parse_note_name(value, middle_c) {
+        Ok(("", note)) => Some(note),
+        _ => None,
+    }
+}
+
+/// Writes a MIDI note number as a note name such as `c#4`, using sharps.
+pub fn note_name(note: u8, middle_c: MiddleC) -> String {
+    const NAMES: [&str; 12] = ["c", "c#", "d", "d#", "e", "f", "f#", "g", "g#", "a", "a#", "b"];
+    let octave = i32::from(note / 12) - 5 + middle_c.octave();
+    format!("{}{octave}", NAMES[usize::from(note % 12)])
+}
+
+/// How a player moves incoming MIDI notes before matching them against
+/// the keys regions are mapped to, as `note_offset` and `octave_offset`
+/// tell it to, and against keyswitches, as `sw_note_offset` and
+/// `sw_octave_offset` do.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KeyTranslation {
+    pub note_offset: i8,
+    pub octave_offset: i8,
+    pub sw_note_offset: i8,
+    pub sw_octave_offset: i8,
+}
+
+impl KeyTranslation {
+    /// The key an incoming note plays, or `None` when the offsets move it
+    /// out of the MIDI note range.
+    pub fn key(&self, note: u8) -> Option<u8> {
+        offset_note(note, self.note_offset, self.octave_offset)
+    }
+
+    /// The note an incoming note is matched against `sw_last`, `sw_down`
+    /// and the other keyswitch opcodes as.
+    pub fn keyswitch(&self, note: u8) -> Option<u8> {
+        offset_note(note, self.sw_note_offset, self.sw_octave_offset)
+    }
+}
+
+fn offset_note(note: u8, semitones: i8, octaves: i8) -> Option<u8> {
+    let note = i16::from(note) + i16::from(semitones) + 12 * i16::from(octaves);
+    u8::try_from(note).ok().filter(|note| *note <= 127)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_keys_and_keyswitches_separately() {
+        let translation = KeyTranslation { note_offset: -2, octave_offset: 1, sw_note_offset: 0, sw_octave_offset: -1 };
+        assert_eq!(translation.key(60), Some(70));
+        assert_eq!(translation.keyswitch(36), Some(24));
+        assert_eq!(KeyTranslation::default().key(60), Some(60));
+    }
+
+    #[test]
+    fn drops_notes_moved_out_of_range() {
+        let translation = KeyTranslation { note_offset: 127, octave_offset: -10, ..KeyTranslation::default() };
+        assert_eq!(translation.key(0), Some(7));
+        assert_eq!(translation.key(1), Some(8));
+        assert_eq!(KeyTranslation { octave_offset: 1, ..translation }.key(116), None);
+        assert_eq!(KeyTranslation { sw_octave_offset: -1, ..translation }.keyswitch(11), None);
+    }
+
+    #[test]
+    fn names_notes_either_side_of_middle_c() {
+        assert_eq!(note_number("c4", MiddleC::C4), Some(60));
+        assert_eq!(note_number("C#3", MiddleC::C3), Some(61));
+        assert_eq!(note_number("eb-1", MiddleC::C4), Some(3));
+        assert_eq!(note_number("g#9", MiddleC::C4), None);
+        assert_eq!(parse_any_note_name("g#9", MiddleC::C4), Ok(("", 128)));
+        assert_eq!(note_name(60, MiddleC::C3), "c3");
+    }
+}
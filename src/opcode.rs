@@ -0,0 +1,727 @@
+use crate::opcode_types::{
+    AmplifierEGParameter, AmplifierLFOParameter, AmplifierParameter, EqualizerParameter,
+    FilterEGParameter, FilterLFOParameter, FilterParameter, InstrumentSettings, InternalConditions,
+    KeyMapping, MidiConditions, PerformanceParameter, PitchEGParameter, PitchLFOParameter,
+    PitchParameter, SamplePlayback, Triggers, VoiceLifecycle,
+};
+use crate::note::{note_number, MiddleC};
+use crate::opcode_key::OpcodeKey;
+use crate::registry::canonical_name;
+use std::any::type_name;
+use std::fmt;
+use std::str::FromStr;
+
+/// A `key=value` pair converted to the variant of the opcode category it
+/// belongs to, with its value parsed to the variant's payload type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Opcode {
+    SamplePlayback(SamplePlayback),
+    InstrumentSettings(InstrumentSettings),
+    VoiceLifecycle(VoiceLifecycle),
+    KeyMapping(KeyMapping),
+    MidiConditions(MidiConditions),
+    InternalConditions(InternalConditions),
+    Triggers(Triggers),
+    Performance(PerformanceParameter),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpcodeError {
+    /// The key isn't an opcode that has a typed representation.
+    Unknown,
+    /// The key is known, but its value can't be parsed to the payload type.
+    InvalidValue { expected: &'static str },
+}
+
+impl fmt::Display for OpcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpcodeError::Unknown => write!(f, "unknown opcode"),
+            OpcodeError::InvalidValue { expected } => write!(f, "expected {expected}"),
+        }
+    }
+}
+
+impl std::error::Error for OpcodeError {}
+
+/// Converts a `key=value` pair to its typed opcode, reading note names
+/// with middle C as `c4`.
+pub fn parse_opcode(key: &str, value: &str) -> Result<Opcode, OpcodeError> {
+    parse_opcode_with(key, value, MiddleC::default())
+}
+
+/// Converts a `key=value` pair to its typed opcode, reading note names
+/// in key opcodes with the given middle C. Aliases are converted to the opcode they stand for.
+pub fn parse_opcode_with(key: &str, value: &str, middle_c: MiddleC) -> Result<Opcode, OpcodeError> {
+    let key = canonical_name(key);
+    let key = key.as_ref();
+    if let Some(opcode) = sample_playback(key, value)? {
+        return Ok(Opcode::SamplePlayback(opcode));
+    }
+    if let Some(opcode) = instrument_settings(key, value)? {
+        return Ok(Opcode::InstrumentSettings(opcode));
+    }
+    if let Some(opcode) = voice_lifecycle(key, value)? {
+        return Ok(Opcode::VoiceLifecycle(opcode));
+    }
+    if let Some(opcode) = key_mapping(key, value, middle_c)? {
+        return Ok(Opcode::KeyMapping(opcode));
+    }
+    if let Some(opcode) = midi_conditions(key, value, middle_c)? {
+        return Ok(Opcode::MidiConditions(opcode));
+    }
+    if let Some(opcode) = internal_conditions(key, value)? {
+        return Ok(Opcode::InternalConditions(opcode));
+    }
+    if let Some(opcode) = triggers(key, value)? {
+        return Ok(Opcode::Triggers(opcode));
+    }
+    if let Some(opcode) = performance_parameter(key, value, middle_c)? {
+        return Ok(Opcode::Performance(opcode));
+    }
+    Err(OpcodeError::Unknown)
+}
+
+/// Converts every opcode that has a typed representation, ordered by key.
+/// Each comes with its key, which holds the numbers the typed opcode
+/// leaves out, such as the CC of `GainCC`. Opcodes which are unknown or
+/// whose values don't parse are skipped; `parse_opcode` tells them apart.
+pub fn typed_opcodes<'a>(
+    opcodes: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> Vec<(OpcodeKey, Opcode)> {
+    let mut opcodes: Vec<(&String, &String)> = opcodes.into_iter().collect();
+    opcodes.sort();
+    opcodes
+        .into_iter()
+        .filter_map(|(key, value)| Some((OpcodeKey::parse(key)?, parse_opcode(key, value).ok()?)))
+        .collect()
+}
+
+fn number<T: FromStr>(value: &str) -> Result<T, OpcodeError> {
+    value.parse().map_err(|_| OpcodeError::InvalidValue { expected: describe::<T>() })
+}
+
+fn note(value: &str, middle_c: MiddleC) -> Result<u8, OpcodeError> {
+    note_number(value, middle_c).ok_or(OpcodeError::InvalidValue { expected: "a note number from 0 to 127 or a note name such as c#4" })
+}
+
+fn text(value: &str) -> Result<String, OpcodeError> {
+    Ok(value.to_owned())
+}
+
+fn describe<T>() -> &'static str {
+    match type_name::<T>() {
+        "u8" => "a whole number from 0 to 255",
+        "u16" => "a whole number from 0 to 65535",
+        "u32" => "a whole number from 0 to 4294967295",
+        "i8" => "a whole number from -128 to 127",
+        "i16" => "a whole number from -32768 to 32767",
+        "i32" => "a whole number",
+        _ => "a number",
+    }
+}
+
+/// The number between `prefix` and `suffix` in `key`, such as `7` for
+/// `amplitude_cc7` with the prefix `amplitude_cc` and no suffix.
+pub(crate) fn index(key: &str, prefix: &str, suffix: &str) -> Option<u16> {
+    let digits = key.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Whether `key` is `prefix` followed by `_onccN`, as in `attack_oncc7`.
+/// Spellings such as `attackcc7` are aliases, canonicalized beforehand.
+fn is_cc(key: &str, prefix: &str) -> bool {
+    index(key, &format!("{prefix}_oncc"), "").is_some()
+}
+
+fn sample_playback(key: &str, value: &str) -> Result<Option<SamplePlayback>, OpcodeError> {
+    use SamplePlayback::*;
+    let opcode = match key {
+        "count" => Count(number(value)?),
+        "delay_beats_random" => DelayBeatsRandom(number(value)?),
+        "delay_beats" => DelayBeats(number(value)?),
+        "delay_random" => DelayRandom(number(value)?),
+        "delay_samples" => DelaySamples(number(value)?),
+        "delay" => Delay(number(value)?),
+        "direction" => Direction(text(value)?),
+        "end" => End(number(value)?),
+        "loop_count" => LoopCount(number(value)?),
+        "loop_crossfade" => LoopCrossfade(number(value)?),
+        "loop_mode" => LoopMode(text(value)?),
+        "loop_start" => LoopStart(number(value)?),
+        "loop_end" => LoopEnd(number(value)?),
+        "loop_tune" => LoopTune(number(value)?),
+        "loop_type" => LoopType(text(value)?),
+        "md5" => Md5(text(value)?),
+        "offset_mode" => OffsetMode(text(value)?),
+        "offset" => Offset(number(value)?),
+        "offset_random" => OffsetRandom(number(value)?),
+        "sample_fadeout" => SampleFadeout(number(value)?),
+        "sample" => Sample(text(value)?),
+        "stop_beats" => StopBeats(number(value)?),
+        "sync_beats" => SyncBeats(number(value)?),
+        "sync_offset" => SyncOffset(number(value)?),
+        "waveguide" => WaveGuide(text(value)?),
+        _ if index(key, "delay_beats_curvecc", "").is_some() => DelayBeatsCurve(number(value)?),
+        _ if index(key, "delay_beats_oncc", "").is_some() => DelayBeatsOn(number(value)?),
+        _ if index(key, "delay_samples_oncc", "").is_some() => DelaySamplesOn(number(value)?),
+        _ if index(key, "delay_curvecc", "").is_some() => DelayCurve(number(value)?),
+        _ if index(key, "delay_oncc", "").is_some() => DelayOn(number(value)?),
+        _ if index(key, "loop_length_oncc", "").is_some() => LoopLengthOn(number(value)?),
+        _ if is_cc(key, "loop_start") => LoopStartCC(number(value)?),
+        _ if index(key, "offset_oncc", "").is_some() => OffsetOn(number(value)?),
+        _ if index(key, "reverse_hicc", "").is_some() => ReverseHighCC(number(value)?),
+        _ if index(key, "reverse_locc", "").is_some() => ReverseLowCC(number(value)?),
+        _ if key.starts_with("sample_dyn_param") && key.contains("_oncc") => {
+            SampleDynParamNOnCC(number(value)?)
+        }
+        _ if index(key, "sample_dyn_param", "").is_some() => SampleDynParam(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn instrument_settings(key: &str, value: &str) -> Result<Option<InstrumentSettings>, OpcodeError> {
+    use InstrumentSettings::*;
+    let opcode = match key {
+        "default_path" => Default(text(value)?),
+        "global_label" => GlobalLabel(text(value)?),
+        "group_label" => GroupLabel(text(value)?),
+        "master_label" => MasterLabel(text(value)?),
+        "region_label" => RegionLabel(text(value)?),
+        "note_offset" => NoteOffset(number(value)?),
+        "octave_offset" => OctaveOffset(number(value)?),
+        "sw_note_offset" => SwNoteOffset(number(value)?),
+        "sw_octave_offset" => SwOctaveOffset(number(value)?),
+        _ if index(key, "label_cc", "").is_some() => LabelCcn(text(value)?),
+        _ if index(key, "label_key", "").is_some() => LabelKey(text(value)?),
+        _ if index(key, "label_output", "").is_some() => LabelOutput(text(value)?),
+        _ if index(key, "set_cc", "").is_some() => SetCcn(number(value)?),
+        _ if index(key, "set_hdcc", "").is_some() => SetHdCC(number(value)?),
+        _ if index(key, "set_realcc", "").is_some() => SetRealCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn voice_lifecycle(key: &str, value: &str) -> Result<Option<VoiceLifecycle>, OpcodeError> {
+    use VoiceLifecycle::*;
+    let opcode = match key {
+        "group" => Group(number(value)?),
+        "note_polyphony" => NotePolyphony(number(value)?),
+        "note_selfmask" => NoteSelfmask(text(value)?),
+        "off_by" => OffBy(number(value)?),
+        "off_mode" => OffMode(text(value)?),
+        "off_curve" => OffCurve(number(value)?),
+        "off_shape" => OffShape(number(value)?),
+        "off_time" => OffTime(number(value)?),
+        "output" => Output(number(value)?),
+        "polyphony_stealing" => PolyphonyStealing(number(value)?),
+        "rt_dead" => RtDead(text(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn key_mapping(key: &str, value: &str, middle_c: MiddleC) -> Result<Option<KeyMapping>, OpcodeError> {
+    use KeyMapping::*;
+    let opcode = match key {
+        "hikey" => HiKey(note(value, middle_c)?),
+        "hivel" => HiVel(number(value)?),
+        "key" => Key(note(value, middle_c)?),
+        "lovel" => LoVel(number(value)?),
+        "lokey" => LoKey(note(value, middle_c)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn midi_conditions(key: &str, value: &str, middle_c: MiddleC) -> Result<Option<MidiConditions>, OpcodeError> {
+    use MidiConditions::*;
+    let opcode = match key {
+        "hibend" => HighBend(number(value)?),
+        "hichan" => HighChan(number(value)?),
+        "hiprog" => HighProg(number(value)?),
+        "lobend" => LowBend(number(value)?),
+        "lochan" => LowChan(number(value)?),
+        "sw_lokey" => SwLowKey(note(value, middle_c)?),
+        "sw_last" => SwLast(note(value, middle_c)?),
+        "sw_down" => SwDown(note(value, middle_c)?),
+        "sw_hikey" => SwHighKey(note(value, middle_c)?),
+        "sw_up" => SwUp(note(value, middle_c)?),
+        "sw_previous" => SwPrevious(note(value, middle_c)?),
+        "sw_vel" => SwVel(text(value)?),
+        "sostenuto_cc" => SostenutoCC(number(value)?),
+        "sostenuto_lo" => SostenutoLow(number(value)?),
+        "sustain_cc" => SustainCC(number(value)?),
+        "sustain_lo" => SustainLow(number(value)?),
+        "sustain_sw" => SustainSw(text(value)?),
+        "sw_default" => SwDefault(note(value, middle_c)?),
+        "sw_hilast" => SwHighLast(note(value, middle_c)?),
+        "sw_label" => SwLabel(text(value)?),
+        "sw_lolast" => SwLowLast(note(value, middle_c)?),
+        _ if index(key, "hicc", "").is_some() => HighCC(number(value)?),
+        _ if index(key, "hihdcc", "").is_some() => HighHdCC(number(value)?),
+        _ if index(key, "locc", "").is_some() => LowCC(number(value)?),
+        _ if index(key, "lohdcc", "").is_some() => LowHdCC(number(value)?),
+        _ if index(key, "var", "_mod").is_some() => VarNNMod(text(value)?),
+        _ if key.starts_with("var") && key.contains("_curvecc") => VarNNCurveCC(number(value)?),
+        _ if key.starts_with("var") && key.contains("_oncc") => VarNNOnCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn internal_conditions(key: &str, value: &str) -> Result<Option<InternalConditions>, OpcodeError> {
+    use InternalConditions::*;
+    let opcode = match key {
+        "hichanaft" => HighChanAft(number(value)?),
+        "hipolyaft" => HighPolyAft(number(value)?),
+        "hibpm" => HighBpm(number(value)?),
+        "hirand" => HighRand(number(value)?),
+        "hitimer" => HighTimer(number(value)?),
+        "lochanaft" => LowChanAft(number(value)?),
+        "lopolyaft" => LowPolyAft(number(value)?),
+        "lorand" => LowRand(number(value)?),
+        "lobpm" => LowBpm(number(value)?),
+        "lotimer" => LowTimer(number(value)?),
+        "seq_length" => SeqLength(number(value)?),
+        "seq_position" => SeqPosition(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn triggers(key: &str, value: &str) -> Result<Option<Triggers>, OpcodeError> {
+    use Triggers::*;
+    let opcode = match key {
+        "trigger" => Trigger(text(value)?),
+        _ if index(key, "start_hicc", "").is_some() => StartHighCC(number(value)?),
+        _ if index(key, "start_hihdcc", "").is_some() => StartHighHdCC(number(value)?),
+        _ if index(key, "start_locc", "").is_some() => StartLowCC(number(value)?),
+        _ if index(key, "start_lohdcc", "").is_some() => StartLowHdCC(number(value)?),
+        _ if index(key, "stop_hicc", "").is_some() => StopHighCC(number(value)?),
+        _ if index(key, "stop_hihdcc", "").is_some() => StopHighHdCC(number(value)?),
+        _ if index(key, "stop_locc", "").is_some() => StopLowCC(number(value)?),
+        _ if index(key, "stop_lohdcc", "").is_some() => StopLowHdCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn performance_parameter(key: &str, value: &str, middle_c: MiddleC) -> Result<Option<PerformanceParameter>, OpcodeError> {
+    if let Some(opcode) = pitch(key, value, middle_c)? {
+        return Ok(Some(PerformanceParameter::Pitch(opcode)));
+    }
+    if let Some(opcode) = pitch_eg(key, value)? {
+        return Ok(Some(PerformanceParameter::PitchEG(opcode)));
+    }
+    if let Some(opcode) = pitch_lfo(key, value)? {
+        return Ok(Some(PerformanceParameter::PitchLFO(opcode)));
+    }
+    if let Some(opcode) = filter(key, value, middle_c)? {
+        return Ok(Some(PerformanceParameter::Filter(opcode)));
+    }
+    if let Some(opcode) = filter_eg(key, value)? {
+        return Ok(Some(PerformanceParameter::FilterEG(opcode)));
+    }
+    if let Some(opcode) = filter_lfo(key, value)? {
+        return Ok(Some(PerformanceParameter::FilterLFO(opcode)));
+    }
+    if let Some(opcode) = amplifier(key, value, middle_c)? {
+        return Ok(Some(PerformanceParameter::Amplifier(opcode)));
+    }
+    if let Some(opcode) = amplifier_eg(key, value)? {
+        return Ok(Some(PerformanceParameter::AmplifierEG(opcode)));
+    }
+    if let Some(opcode) = amplifier_lfo(key, value)? {
+        return Ok(Some(PerformanceParameter::AmplifierLFO(opcode)));
+    }
+    if let Some(opcode) = equalizer(key, value)? {
+        return Ok(Some(PerformanceParameter::Equalizer(opcode)));
+    }
+    Ok(None)
+}
+
+fn pitch(key: &str, value: &str, middle_c: MiddleC) -> Result<Option<PitchParameter>, OpcodeError> {
+    use PitchParameter::*;
+    let opcode = match key {
+        "transpose" => Transpose(number(value)?),
+        "tune" => Tune(number(value)?),
+        "pitch_keycenter" => KeyCenter(note(value, middle_c)?),
+        "pitch_keytrack" => KeyTrack(number(value)?),
+        "pitch_veltrack" => VelTrack(number(value)?),
+        "pitch_random" => Random(number(value)?),
+        "bend_up" => BendUp(number(value)?),
+        "bend_down" => BendDown(number(value)?),
+        "bend_step" => BendStep(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn pitch_eg(key: &str, value: &str) -> Result<Option<PitchEGParameter>, OpcodeError> {
+    use PitchEGParameter::*;
+    let Some(stage) = key.strip_prefix("pitcheg_") else {
+        return Ok(None);
+    };
+    let opcode = match stage {
+        "delay" => Delay(number(value)?),
+        "start" => Start(number(value)?),
+        "attack" => Attack(number(value)?),
+        "hold" => Hold(number(value)?),
+        "decay" => Decay(number(value)?),
+        "sustain" => Sustain(number(value)?),
+        "release" => Release(number(value)?),
+        "depth" => Depth(number(value)?),
+        "vel2delay" => Vel2Delay(number(value)?),
+        "vel2attack" => Vel2Attack(number(value)?),
+        "vel2hold" => Vel2Hold(number(value)?),
+        "vel2decay" => Vel2Decay(number(value)?),
+        "vel2sustain" => Vel2Sustain(number(value)?),
+        "vel2release" => Vel2Release(number(value)?),
+        "vel2depth" => Vel2Depth(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn pitch_lfo(key: &str, value: &str) -> Result<Option<PitchLFOParameter>, OpcodeError> {
+    use PitchLFOParameter::*;
+    let Some(parameter) = key.strip_prefix("pitchlfo_") else {
+        return Ok(None);
+    };
+    let opcode = match parameter {
+        "delay" => Delay(number(value)?),
+        "fade" => Fade(number(value)?),
+        "freq" => Freq(number(value)?),
+        "depth" => Depth(number(value)?),
+        "depthchanaft" => DepthChanAft(number(value)?),
+        "depthpolyaft" => DepthPolyAft(number(value)?),
+        "freqchanaft" => FreqChanAft(number(value)?),
+        "freqpolyaft" => FreqPolyAft(number(value)?),
+        _ if is_cc(parameter, "depth") => DepthCC(number(value)?),
+        _ if is_cc(parameter, "freq") => FreqCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn filter(key: &str, value: &str, middle_c: MiddleC) -> Result<Option<FilterParameter>, OpcodeError> {
+    use FilterParameter::*;
+    let opcode = match key {
+        "fil_type" => FilType(text(value)?),
+        "cutoff" => Cutoff(number(value)?),
+        "cutoff_chanaft" => CutoffChanAft(number(value)?),
+        "cutoff_polyaft" => CutoffPolyAft(number(value)?),
+        "resonance" => Resonance(number(value)?),
+        "fil_keytrack" => KeyTrack(number(value)?),
+        "fil_keycenter" => KeyCenter(note(value, middle_c)?),
+        "fil_veltrack" => VelTrack(number(value)?),
+        "fil_random" => Random(number(value)?),
+        _ if index(key, "cutoff_oncc", "").is_some() => CutoffCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn filter_eg(key: &str, value: &str) -> Result<Option<FilterEGParameter>, OpcodeError> {
+    use FilterEGParameter::*;
+    let Some(stage) = key.strip_prefix("fileg_") else {
+        return Ok(None);
+    };
+    let opcode = match stage {
+        "delay" => Delay(number(value)?),
+        "start" => Start(number(value)?),
+        "attack" => Attack(number(value)?),
+        "hold" => Hold(number(value)?),
+        "decay" => Decay(number(value)?),
+        "sustain" => Sustain(number(value)?),
+        "release" => Release(number(value)?),
+        "depth" => Depth(number(value)?),
+        "vel2delay" => Vel2Delay(number(value)?),
+        "vel2attack" => Vel2Attack(number(value)?),
+        "vel2hold" => Vel2Hold(number(value)?),
+        "vel2decay" => Vel2Decay(number(value)?),
+        "vel2sustain" => Vel2Sustain(number(value)?),
+        "vel2release" => Vel2Release(number(value)?),
+        "vel2depth" => Vel2Depth(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn filter_lfo(key: &str, value: &str) -> Result<Option<FilterLFOParameter>, OpcodeError> {
+    use FilterLFOParameter::*;
+    let Some(parameter) = key.strip_prefix("fillfo_") else {
+        return Ok(None);
+    };
+    let opcode = match parameter {
+        "delay" => Delay(number(value)?),
+        "fade" => Fade(number(value)?),
+        "freq" => Freq(number(value)?),
+        "depth" => Depth(number(value)?),
+        "depthchanaft" => DepthChanAft(number(value)?),
+        "depthpolyaft" => DepthPolyAft(number(value)?),
+        "freqchanaft" => FreqChanAft(number(value)?),
+        "freqpolyaft" => FreqPolyAft(number(value)?),
+        _ if is_cc(parameter, "depth") => DepthCC(number(value)?),
+        _ if is_cc(parameter, "freq") => FreqCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn amplifier(key: &str, value: &str, middle_c: MiddleC) -> Result<Option<AmplifierParameter>, OpcodeError> {
+    use AmplifierParameter::*;
+    let opcode = match key {
+        "amp_keycenter" => AmpKeyCenter(note(value, middle_c)?),
+        "amp_keytrack" => AmpKeyTrack(number(value)?),
+        "amp_random" => AmpRandom(number(value)?),
+        "amp_veltrack_random" => AmpVelTrackRandom(number(value)?),
+        "amp_veltrack" => AmpVelTrack(number(value)?),
+        "amplitude" => Amplitude(number(value)?),
+        "global_amplitude" => GlobalAmplitude(number(value)?),
+        "global_volume" => GlobalVolume(number(value)?),
+        "group_amplitude" => GroupAmplitude(number(value)?),
+        "group_volume" => GroupVolume(number(value)?),
+        "master_amplitude" => MasterAmplitude(number(value)?),
+        "master_volume" => MasterVolume(number(value)?),
+        "pan_keycenter" => PanKeyCenter(note(value, middle_c)?),
+        "pan_keytrack" => PanKeyTrack(number(value)?),
+        "pan_law" => PanLaw(text(value)?),
+        "pan_random" => PanRandom(number(value)?),
+        "pan_veltrack" => PanVelTrack(number(value)?),
+        "pan" => Pan(number(value)?),
+        "phase" => Phase(text(value)?),
+        "position_keycenter" => PositionKeyCenter(note(value, middle_c)?),
+        "position_keytrack" => PositionKeyTrack(number(value)?),
+        "position_random" => PositionRandom(number(value)?),
+        "position_veltrack" => PositionVelTrack(number(value)?),
+        "position" => Position(number(value)?),
+        "rt_decay" => RtDecay(number(value)?),
+        "volume" => Volume(number(value)?),
+        "width" => Width(number(value)?),
+        "xfin_lokey" => CfInLowKey(note(value, middle_c)?),
+        "xfin_hikey" => CfInHighKey(note(value, middle_c)?),
+        "xfout_lokey" => CfOutLowKey(note(value, middle_c)?),
+        "xfout_hikey" => CfOutHighKey(note(value, middle_c)?),
+        "xf_keycurve" => CfKeyCurve(text(value)?),
+        "xfin_lovel" => CfInLowVel(number(value)?),
+        "xfin_hivel" => CfInHighVel(number(value)?),
+        "xfout_lovel" => CfOutLowVel(number(value)?),
+        "xfout_hivel" => CfOutHighVel(number(value)?),
+        "xf_velcurve" => CfVelCurve(text(value)?),
+        "xf_cccurve" => CfCCCurve(text(value)?),
+        _ if index(key, "amp_velcurve_", "").is_some() => AmpVelCurve(number(value)?),
+        _ if index(key, "amp_veltrack_curvecc", "").is_some() => AmplVelTrackCurveCC(number(value)?),
+        _ if index(key, "amp_veltrack_oncc", "").is_some() => AmpVelTrackCC(number(value)?),
+        _ if index(key, "amplitude_curvecc", "").is_some() => AmplitudeCurveCC(number(value)?),
+        _ if index(key, "amplitude_smoothcc", "").is_some() => AmplitudeSmoothCC(number(value)?),
+        _ if index(key, "amplitude_oncc", "").is_some() => AmplitudeOnCC(number(value)?),
+        _ if index(key, "pan_curvecc", "").is_some() => PanCurveCC(number(value)?),
+        _ if index(key, "pan_smoothcc", "").is_some() => PanSmoothCC(number(value)?),
+        _ if index(key, "pan_stepcc", "").is_some() => PanStepCC(number(value)?),
+        _ if index(key, "pan_oncc", "").is_some() => PanOnCC(number(value)?),
+        _ if index(key, "position_curvecc", "").is_some() => PositionCurveCC(number(value)?),
+        _ if index(key, "position_smoothcc", "").is_some() => PositionSmoothCC(number(value)?),
+        _ if index(key, "position_stepcc", "").is_some() => PositionStepCC(number(value)?),
+        _ if index(key, "position_oncc", "").is_some() => PositionOnCC(number(value)?),
+        _ if index(key, "rt_decay", "_time").is_some() => RtDecayNTime(number(value)?),
+        _ if index(key, "rt_decay", "").is_some() => RTDecayN(number(value)?),
+        _ if index(key, "volume_curvecc", "").is_some() => VolumeCurveCC(number(value)?),
+        _ if index(key, "volume_smoothcc", "").is_some() => VolumeSmoothCC(number(value)?),
+        _ if index(key, "volume_stepcc", "").is_some() => VolumeStepCC(number(value)?),
+        _ if index(key, "volume_oncc", "").is_some() => VolumeOnCC(number(value)?),
+        _ if index(key, "width_curvecc", "").is_some() => WidthCurveCC(number(value)?),
+        _ if index(key, "width_stepcc", "").is_some() => WidthStepCC(number(value)?),
+        _ if index(key, "width_oncc", "").is_some() => WidthOnCC(number(value)?),
+        _ if index(key, "xfin_locc", "").is_some() => CfInLowCC(number(value)?),
+        _ if index(key, "xfin_hicc", "").is_some() => CfInHighCC(number(value)?),
+        _ if index(key, "xfout_locc", "").is_some() => CfOutLowCC(number(value)?),
+        _ if index(key, "xfout_hicc", "").is_some() => CfOutHighCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn amplifier_eg(key: &str, value: &str) -> Result<Option<AmplifierEGParameter>, OpcodeError> {
+    use AmplifierEGParameter::*;
+    let Some(stage) = key.strip_prefix("ampeg_") else {
+        return Ok(None);
+    };
+    let opcode = match stage {
+        "delay" => Delay(number(value)?),
+        "start" => Start(number(value)?),
+        "attack" => Attack(number(value)?),
+        "hold" => Hold(number(value)?),
+        "decay" => Decay(number(value)?),
+        "sustain" => Sustain(number(value)?),
+        "release" => Release(number(value)?),
+        "vel2delay" => Vel2Delay(number(value)?),
+        "vel2attack" => Vel2Attack(number(value)?),
+        "vel2hold" => Vel2Hold(number(value)?),
+        "vel2decay" => Vel2Decay(number(value)?),
+        "vel2sustain" => Vel2Sustain(number(value)?),
+        "vel2release" => Vel2Release(number(value)?),
+        _ if is_cc(stage, "delay") => DelayCC(number(value)?),
+        _ if is_cc(stage, "start") => StartCC(number(value)?),
+        _ if is_cc(stage, "attack") => AttackCC(number(value)?),
+        _ if is_cc(stage, "hold") => HoldCC(number(value)?),
+        _ if is_cc(stage, "decay") => DecayCC(number(value)?),
+        _ if is_cc(stage, "sustain") => SustainCC(number(value)?),
+        _ if is_cc(stage, "release") => ReleaseCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn amplifier_lfo(key: &str, value: &str) -> Result<Option<AmplifierLFOParameter>, OpcodeError> {
+    use AmplifierLFOParameter::*;
+    let Some(parameter) = key.strip_prefix("amplfo_") else {
+        return Ok(None);
+    };
+    let opcode = match parameter {
+        "delay" => Delay(number(value)?),
+        "fade" => Fade(number(value)?),
+        "freq" => Freq(number(value)?),
+        "depth" => Depth(number(value)?),
+        "depthchanaft" => DepthChanAft(number(value)?),
+        "depthpolyaft" => DepthPolyAft(number(value)?),
+        "freqchanaft" => FreqChanAft(number(value)?),
+        "freqpolyaft" => FreqPolyAft(number(value)?),
+        _ if is_cc(parameter, "depth") => DepthCC(number(value)?),
+        _ if is_cc(parameter, "freq") => FreqCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+fn equalizer(key: &str, value: &str) -> Result<Option<EqualizerParameter>, OpcodeError> {
+    use EqualizerParameter::*;
+    let opcode = match key {
+        "eq1_freq" => Eq1Freq(number(value)?),
+        "eq2_freq" => Eq2Freq(number(value)?),
+        "eq3_freq" => Eq3Freq(number(value)?),
+        "eq1_vel2freq" => Eq1Vel2Freq(number(value)?),
+        "eq2_vel2freq" => Eq2Vel2Freq(number(value)?),
+        "eq3_vel2freq" => Eq3Vel2Freq(number(value)?),
+        "eq1_bw" => Eq1Bandwidth(number(value)?),
+        "eq2_bw" => Eq2Bandwidth(number(value)?),
+        "eq3_bw" => Eq3Bandwidth(number(value)?),
+        "eq1_gain" => Eq1Gain(number(value)?),
+        "eq2_gain" => Eq2Gain(number(value)?),
+        "eq3_gain" => Eq3Gain(number(value)?),
+        "eq1_vel2gain" => Eq1Vel2Gain(number(value)?),
+        "eq2_vel2gain" => Eq2Vel2Gain(number(value)?),
+        "eq3_vel2gain" => Eq3Vel2Gain(number(value)?),
+        _ if is_cc(key, "eq1_freq") => Eq1FreqCC(number(value)?),
+        _ if is_cc(key, "eq2_freq") => Eq2FreqCC(number(value)?),
+        _ if is_cc(key, "eq3_freq") => Eq3FreqCC(number(value)?),
+        _ if is_cc(key, "eq1_bw") => Eq1BandwidthCC(number(value)?),
+        _ if is_cc(key, "eq2_bw") => Eq2BandwidthCC(number(value)?),
+        _ if is_cc(key, "eq3_bw") => Eq3BandwidthCC(number(value)?),
+        _ if is_cc(key, "eq1_gain") => Eq1GainCC(number(value)?),
+        _ if is_cc(key, "eq2_gain") => Eq2GainCC(number(value)?),
+        _ if is_cc(key, "eq3_gain") => Eq3GainCC(number(value)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(opcode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn converts_opcodes_of_each_category() {
+        let opcodes = [
+            ("sample", "piano/c4.wav", Opcode::SamplePlayback(SamplePlayback::Sample("piano/c4.wav".to_owned()))),
+            ("offset", "200", Opcode::SamplePlayback(SamplePlayback::Offset(200))),
+            ("region_label", "Soft", Opcode::InstrumentSettings(InstrumentSettings::RegionLabel("Soft".to_owned()))),
+            ("set_cc7", "100", Opcode::InstrumentSettings(InstrumentSettings::SetCcn(100))),
+            ("group", "-2", Opcode::VoiceLifecycle(VoiceLifecycle::Group(-2))),
+            ("lokey", "36", Opcode::KeyMapping(KeyMapping::LoKey(36))),
+            ("lochan", "2", Opcode::MidiConditions(MidiConditions::LowChan(2))),
+            ("hicc64", "63", Opcode::MidiConditions(MidiConditions::HighCC(63))),
+            ("seq_position", "3", Opcode::InternalConditions(InternalConditions::SeqPosition(3))),
+            ("trigger", "release", Opcode::Triggers(Triggers::Trigger("release".to_owned()))),
+            ("start_locc1", "10", Opcode::Triggers(Triggers::StartLowCC(10))),
+        ];
+        for (key, value, opcode) in opcodes {
+            assert_eq!(parse_opcode(key, value), Ok(opcode), "{key}={value}");
+        }
+
+        let parameters = [
+            ("transpose", "-12", PerformanceParameter::Pitch(PitchParameter::Transpose(-12))),
+            ("pitchlfo_depth_oncc1", "50", PerformanceParameter::PitchLFO(PitchLFOParameter::DepthCC(50))),
+            ("cutoff_oncc74", "1200", PerformanceParameter::Filter(FilterParameter::CutoffCC(1200))),
+            ("fileg_attack", "0.5", PerformanceParameter::FilterEG(FilterEGParameter::Attack(0.5))),
+            ("volume", "-6", PerformanceParameter::Amplifier(AmplifierParameter::Volume(-6.0))),
+            ("ampeg_attack", "0.01", PerformanceParameter::AmplifierEG(AmplifierEGParameter::Attack(0.01))),
+            ("amplfo_freq", "5", PerformanceParameter::AmplifierLFO(AmplifierLFOParameter::Freq(5.0))),
+            ("eq1_freq", "800", PerformanceParameter::Equalizer(EqualizerParameter::Eq1Freq(800.0))),
+        ];
+        for (key, value, parameter) in parameters {
+            assert_eq!(parse_opcode(key, value), Ok(Opcode::Performance(parameter)), "{key}={value}");
+        }
+    }
+
+    #[test]
+    fn tells_unknown_opcodes_from_invalid_values() {
+        assert_eq!(parse_opcode("made_up_opcode", "1"), Err(OpcodeError::Unknown));
+        assert_eq!(parse_opcode("cutoff_oncc", "1"), Err(OpcodeError::Unknown));
+        let error = parse_opcode("offset", "-1").unwrap_err();
+        assert_eq!(error, OpcodeError::InvalidValue { expected: "a whole number from 0 to 4294967295" });
+        assert_eq!(error.to_string(), "expected a whole number from 0 to 4294967295");
+        assert!(matches!(parse_opcode("lokey", "h4"), Err(OpcodeError::InvalidValue { .. })));
+        assert!(matches!(parse_opcode("volume", "loud"), Err(OpcodeError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn reads_note_names_with_either_middle_c() {
+        assert_eq!(parse_opcode("lokey", "c4"), Ok(Opcode::KeyMapping(KeyMapping::LoKey(60))));
+        assert_eq!(parse_opcode_with("lokey", "c4", MiddleC::C4), Ok(Opcode::KeyMapping(KeyMapping::LoKey(60))));
+        assert_eq!(parse_opcode_with("lokey", "c4", MiddleC::C3), Ok(Opcode::KeyMapping(KeyMapping::LoKey(72))));
+        assert_eq!(
+            parse_opcode_with("sw_last", "c#3", MiddleC::C3),
+            Ok(Opcode::MidiConditions(MidiConditions::SwLast(61)))
+        );
+        assert_eq!(
+            parse_opcode_with("pitch_keycenter", "60", MiddleC::C3),
+            Ok(Opcode::Performance(PerformanceParameter::Pitch(PitchParameter::KeyCenter(60))))
+        );
+        assert_eq!(parse_opcode_with("hikey", "g9", MiddleC::C4), Ok(Opcode::KeyMapping(KeyMapping::HiKey(127))));
+        assert!(parse_opcode_with("hikey", "g9", MiddleC::C3).is_err());
+    }
+
+    #[test]
+    fn converts_aliases_to_the_opcode_they_stand_for() {
+        assert_eq!(parse_opcode("polyphony_group", "3"), Ok(Opcode::VoiceLifecycle(VoiceLifecycle::Group(3))));
+        assert_eq!(
+            parse_opcode("loopmode", "one_shot"),
+            Ok(Opcode::SamplePlayback(SamplePlayback::LoopMode("one_shot".to_owned())))
+        );
+        assert_eq!(
+            parse_opcode("gain_cc7", "6"),
+            Ok(Opcode::Performance(PerformanceParameter::Amplifier(AmplifierParameter::VolumeOnCC(6.0))))
+        );
+    }
+
+    #[test]
+    fn orders_typed_opcodes_by_key_and_skips_the_rest() {
+        let opcodes: HashMap<String, String> =
+            [("volume", "-3"), ("lokey", "60"), ("made_up_opcode", "1"), ("offset", "x"), ("amplitude_oncc7", "50")]
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect();
+        let typed = typed_opcodes(&opcodes);
+        let keys: Vec<String> = typed.iter().map(|(key, _)| key.to_string()).collect();
+        assert_eq!(keys, ["amplitude_oncc7", "lokey", "volume"]);
+        assert_eq!(typed[1].1, Opcode::KeyMapping(KeyMapping::LoKey(60)));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::registry::{canonical_name, lookup, pattern_numbers};
+use nom::{
+    branch::alt,
+    bytes::complete::take_while1,
+    character::complete::digit1,
+    combinator::{all_consuming, map},
+    multi::many1,
+    IResult,
+};
+use std::fmt;
+
+/// A number embedded in an opcode's name, tagged with what it counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OpcodeIndex {
+    /// A MIDI CC, as in `cutoff_oncc74`.
+    Cc(u16),
+    /// An envelope generator, as in `eg01_time2`.
+    Eg(u16),
+    /// A point of an envelope generator, as in `eg01_time2`.
+    EgPoint(u16),
+    /// An LFO, as in `lfo02_freq`.
+    Lfo(u16),
+    /// An equalizer band, as in `eq2_gain`.
+    EqBand(u16),
+    /// A velocity, as in `amp_velcurve_64`.
+    Velocity(u16),
+    /// A key, as in `label_key60`.
+    Key(u16),
+    /// An output, as in `label_output1`.
+    Output(u16),
+    /// A point of a `<curve>`, as in `v064`.
+    CurvePoint(u16),
+    /// An ARIA variable, as in `var01_mod`.
+    Variable(u16),
+    /// Any other number, as in `rt_decay1`.
+    Other(u16),
+}
+
+impl OpcodeIndex {
+    pub fn number(&self) -> u16 {
+        match *self {
+            OpcodeIndex::Cc(number)
+            | OpcodeIndex::Eg(number)
+            | OpcodeIndex::EgPoint(number)
+            | OpcodeIndex::Lfo(number)
+            | OpcodeIndex::EqBand(number)
+            | OpcodeIndex::Velocity(number)
+            | OpcodeIndex::Key(number)
+            | OpcodeIndex::Output(number)
+            | OpcodeIndex::CurvePoint(number)
+            | OpcodeIndex::Variable(number)
+            | OpcodeIndex::Other(number) => number,
+        }
+    }
+
+    /// Works out what a number counts from the text right before it.
+    fn new(preceding: &str, number: u16) -> OpcodeIndex {
+        if preceding.ends_with("cc") {
+            OpcodeIndex::Cc(number)
+        } else if preceding.ends_with("lfo") {
+            OpcodeIndex::Lfo(number)
+        } else if preceding.ends_with("eg") {
+            OpcodeIndex::Eg(number)
+        } else if ["_time", "_level", "_shape", "_curve"].iter().any(|point| preceding.ends_with(point)) {
+            OpcodeIndex::EgPoint(number)
+        } else if preceding.ends_with("eq") {
+            OpcodeIndex::EqBand(number)
+        } else if preceding.ends_with("velcurve_") {
+            OpcodeIndex::Velocity(number)
+        } else if preceding.ends_with("label_key") {
+            OpcodeIndex::Key(number)
+        } else if preceding.ends_with("label_output") {
+            OpcodeIndex::Output(number)
+        } else if preceding == "v" {
+            OpcodeIndex::CurvePoint(number)
+        } else if preceding.ends_with("var") {
+            OpcodeIndex::Variable(number)
+        } else {
+            OpcodeIndex::Other(number)
+        }
+    }
+}
+
+/// An opcode's name split into its family and the numbers embedded in it,
+/// so `lfo02_freq_smoothcc1` is the family `lfoN_freq_smoothccN` with
+/// LFO 2 and CC 1.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OpcodeKey {
+    /// The canonical name with `N` in place of each number, as in `opcodes.tsv`.
+    pub base: String,
+    /// The numbers, in the order they appear in the name.
+    pub indices: Vec<OpcodeIndex>,
+    /// How many digits each number was written with, so zero padding, as
+    /// in `lfo02` or `v064`, is written back out as it was.
+    pub widths: Vec<usize>,
+}
+
+impl OpcodeKey {
+    /// Splits an opcode's name, resolving aliases first. Families in the
+    /// opcode registry are split where the registry has an `N`; any other
+    /// name is split at each number followed by `_` or the end of the name.
+    pub fn parse(key: &str) -> Option<OpcodeKey> {
+        let canonical = canonical_name(key);
+        let canonical = canonical.as_ref();
+        match lookup(canonical) {
+            Some(info) if info.name.contains('N') => {
+                let numbers = pattern_numbers(info.name, canonical)?;
+                let widths = numbers.iter().map(|number| number.len()).collect();
+                let indices = info
+                    .name
+                    .split('N')
+                    .zip(numbers)
+                    .map(|(preceding, number)| Some(OpcodeIndex::new(preceding, number.parse().ok()?)))
+                    .collect::<Option<_>>()?;
+                return Some(OpcodeKey { base: info.name.to_owned(), indices, widths });
+            }
+            // Names such as `md5` or `effect1` end in a number that isn't an index.
+            Some(info) if info.name.ends_with(|c: char| c.is_ascii_digit()) => {
+                return Some(OpcodeKey { base: info.name.to_owned(), indices: vec![], widths: vec![] });
+            }
+            _ => {}
+        }
+        let parsed = all_consuming(indexed_name)(canonical);
+        parsed.ok().map(|(_, key)| key)
+    }
+
+    /// The first CC number in the name, if any.
+    pub fn cc(&self) -> Option<u16> {
+        self.indices.iter().find_map(|index| match index {
+            OpcodeIndex::Cc(cc) => Some(*cc),
+            _ => None,
+        })
+    }
+
+    pub fn lfo(&self) -> Option<u16> {
+        self.indices.iter().find_map(|index| match index {
+            OpcodeIndex::Lfo(lfo) => Some(*lfo),
+            _ => None,
+        })
+    }
+
+    pub fn eg(&self) -> Option<u16> {
+        self.indices.iter().find_map(|index| match index {
+            OpcodeIndex::Eg(eg) => Some(*eg),
+            _ => None,
+        })
+    }
+
+    pub fn eq_band(&self) -> Option<u16> {
+        self.indices.iter().find_map(|index| match index {
+            OpcodeIndex::EqBand(band) => Some(*band),
+            _ => None,
+        })
+    }
+}
+
+/// Writes the canonical name, with the numbers in place of each `N`
+/// padded to the width they were written with.
+impl fmt::Display for OpcodeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut indices = self.indices.iter().enumerate();
+        for (position, piece) in self.base.split('N').enumerate() {
+            if position > 0 {
+                match indices.next() {
+                    Some((number, index)) => {
+                        let width = self.widths.get(number).copied().unwrap_or(0);
+                        write!(f, "{:0width$}", index.number())?;
+                    }
+                    None => write!(f, "N")?,
+                }
+            }
+            write!(f, "{piece}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A run of letters and underscores, or a run of digits.
+enum Piece<'a> {
+    Text(&'a str),
+    Number(&'a str),
+}
+
+fn piece(sfz_source: &str) -> IResult<&str, Piece<'_>> {
+    alt((
+        map(take_while1(|c: char| c.is_alphabetic() || c == '_'), Piece::Text),
+        map(digit1, |digits: &str| Piece::Number(digits)),
+    ))(sfz_source)
+}
+
+/// Parses an opcode name outside of the registry into a key.
+pub fn indexed_name(sfz_source: &str) -> IResult<&str, OpcodeKey> {
+    let (remaining, pieces) = many1(piece)(sfz_source)?;
+
+    let mut key = OpcodeKey { base: String::new(), indices: vec![], widths: vec![] };
+    let mut preceding = String::new();
+    for (position, piece) in pieces.iter().enumerate() {
+        match piece {
+            Piece::Text(text) => preceding.push_str(text),
+            Piece::Number(digits) => {
+                // Numbers within a word, as in `vel2attack`, are part of the name.
+                let ends_word = match pieces.get(position + 1) {
+                    Some(Piece::Text(text)) => text.starts_with('_'),
+                    _ => true,
+                };
+                match digits.parse() {
+                    Ok(number) if ends_word => {
+                        key.indices.push(OpcodeIndex::new(&preceding, number));
+                        key.widths.push(digits.len());
+                        key.base.push_str(&preceding);
+                        key.base.push('N');
+                        preceding.clear();
+                    }
+                    _ => preceding.push_str(digits),
+                }
+            }
+        }
+    }
+    key.base.push_str(&preceding);
+    Ok((remaining, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_indexed_opcodes_into_families() {
+        let key = OpcodeKey::parse("cutoff_oncc74").unwrap();
+        assert_eq!((key.base.as_str(), key.cc()), ("cutoff_onccN", Some(74)));
+        let key = OpcodeKey::parse("amplitude_curvecc11").unwrap();
+        assert_eq!(key.base, "amplitude_curveccN");
+        assert_eq!(key.indices, [OpcodeIndex::Cc(11)]);
+        let key = OpcodeKey::parse("lfo02_freq_smoothcc1").unwrap();
+        assert_eq!(key.base, "lfoN_freq_smoothccN");
+        assert_eq!(key.indices, [OpcodeIndex::Lfo(2), OpcodeIndex::Cc(1)]);
+        let key = OpcodeKey::parse("eg01_time2").unwrap();
+        assert_eq!(key.indices, [OpcodeIndex::Eg(1), OpcodeIndex::EgPoint(2)]);
+    }
+
+    #[test]
+    fn keeps_numbers_that_arent_indices_in_the_name() {
+        assert_eq!(OpcodeKey::parse("md5").unwrap().indices, []);
+        assert_eq!(OpcodeKey::parse("fileg_vel2attack").unwrap().base, "fileg_vel2attack");
+        assert_eq!(OpcodeKey::parse("sample").unwrap().to_string(), "sample");
+    }
+
+    #[test]
+    fn writes_numbers_with_their_zero_padding() {
+        for name in ["lfo02_freq_smoothcc1", "v064", "eg01_time2", "cutoff_oncc74", "made_up07_opcode"] {
+            assert_eq!(OpcodeKey::parse(name).unwrap().to_string(), name);
+        }
+        assert_eq!(OpcodeKey::parse("v064").unwrap().indices, [OpcodeIndex::CurvePoint(64)]);
+        assert_ne!(OpcodeKey::parse("lfo02_freq"), OpcodeKey::parse("lfo2_freq"));
+    }
+
+    #[test]
+    fn parses_names_outside_of_the_registry() {
+        let (_, key) = indexed_name("made_up07_opcode_cc3").unwrap();
+        assert_eq!(key.base, "made_upN_opcode_ccN");
+        assert_eq!(key.indices, [OpcodeIndex::Other(7), OpcodeIndex::Cc(3)]);
+    }
+}
@@ -22,9 +22,10 @@
 // lo -> Low
 // hi -> High
 //
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum InputControl {}
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum Triggers {
     Trigger(String), // Can be attack, release, first or legato.
     OnHighCC(u8),
@@ -40,10 +41,11 @@ pub enum Triggers {
     StopLowCC(u32),
     StopLowHdCC(f32),
 }
+#[derive(Clone, Debug, PartialEq)]
 pub enum SamplePlayback {
     Count(u32),
     DelayBeatsCurve(u8),
-    DelayBeatsOn(String),
+    DelayBeatsOn(f32),
     DelayBeatsRandom(f32),
     DelayBeats(f32),
     DelayCC(f32),
@@ -57,8 +59,8 @@ pub enum SamplePlayback {
     End(u32),
     LoopCount(u32),
     LoopCrossfade(f32),
-    LoopLengthOn(String),
-    LoopLengthCC(String),
+    LoopLengthOn(u32),
+    LoopLengthCC(u32),
     LoopMode(String), // Can be no_loop, one_shot, loop_continuous, loop_sustain
     LoopStart(u32),
     LoopStartCC(u32),
@@ -83,6 +85,7 @@ pub enum SamplePlayback {
     WaveGuide(String),
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum InstrumentSettings {
     Mod(String),
     Default(String),
@@ -105,7 +108,9 @@ pub enum InstrumentSettings {
     SwOctaveOffset(i8),
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum VoiceLifecycle {
+    /// `group`, or its ARIA alias `polyphony_group`.
     Group(i32),
     NotePolyphony(u32),
     NoteSelfmask(String),
@@ -115,19 +120,20 @@ pub enum VoiceLifecycle {
     OffShape(f32),
     OffTime(f32),
     Output(u16),
-    PolyphonyGroup(i32),
     PolyphonyStealing(u32),
     RtDead(String),
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum KeyMapping {
     HiKey(u8),
     HiVel(u8),
-    Key,
+    Key(u8),
     LoVel(u8),
     LoKey(u8),
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum MidiConditions {
     HighBend(i16),
     HighCC(u8),
@@ -160,6 +166,7 @@ pub enum MidiConditions {
     VarNNOnCC(f32),
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum InternalConditions {
     HighChanAft(u8),
     HighPolyAft(u8),
@@ -174,7 +181,7 @@ pub enum InternalConditions {
     SeqLength(u8),
     SeqPosition(u8),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum BusOption {
     Main,
     Aux1,
@@ -191,7 +198,48 @@ pub enum BusOption {
     Fx4,
     Midi,
 }
-#[derive(Clone, Debug)]
+
+impl BusOption {
+    pub fn from_name(name: &str) -> Option<BusOption> {
+        match name {
+            "main" => Some(BusOption::Main),
+            "aux1" => Some(BusOption::Aux1),
+            "aux2" => Some(BusOption::Aux2),
+            "aux3" => Some(BusOption::Aux3),
+            "aux4" => Some(BusOption::Aux4),
+            "aux5" => Some(BusOption::Aux5),
+            "aux6" => Some(BusOption::Aux6),
+            "aux7" => Some(BusOption::Aux7),
+            "aux8" => Some(BusOption::Aux8),
+            "fx1" => Some(BusOption::Fx1),
+            "fx2" => Some(BusOption::Fx2),
+            "fx3" => Some(BusOption::Fx3),
+            "fx4" => Some(BusOption::Fx4),
+            "midi" => Some(BusOption::Midi),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BusOption::Main => "main",
+            BusOption::Aux1 => "aux1",
+            BusOption::Aux2 => "aux2",
+            BusOption::Aux3 => "aux3",
+            BusOption::Aux4 => "aux4",
+            BusOption::Aux5 => "aux5",
+            BusOption::Aux6 => "aux6",
+            BusOption::Aux7 => "aux7",
+            BusOption::Aux8 => "aux8",
+            BusOption::Fx1 => "fx1",
+            BusOption::Fx2 => "fx2",
+            BusOption::Fx3 => "fx3",
+            BusOption::Fx4 => "fx4",
+            BusOption::Midi => "midi",
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
 pub enum SamplePlayerParameter {
     Delay(f32),
     DelayRandom(f32),
@@ -202,19 +250,19 @@ pub enum SamplePlayerParameter {
     SyncBeats(f32),
     SyncOffset(f32),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PitchParameter {
-    Transpose(u8),
-    Tune(u8),
+    Transpose(i8),
+    Tune(i16),
     KeyCenter(u8),
-    KeyTrack(u16),
-    VelTrack(u16),
+    KeyTrack(i16),
+    VelTrack(i16),
     Random(u16),
-    BendUp(u16),
-    BendDown(u16),
+    BendUp(i16),
+    BendDown(i16),
     BendStep(u16),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PitchEGParameter {
     Delay(f32),
     Start(f32),
@@ -230,36 +278,36 @@ pub enum PitchEGParameter {
     Vel2Decay(f32),
     Vel2Sustain(f32),
     Vel2Release(f32),
-    Vel2Depth(u32),
+    Vel2Depth(i16),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 
 pub enum PitchLFOParameter {
     Delay(f32),
     Fade(f32),
     Freq(f32),
-    Depth(u16),
-    DepthCC(u16),
-    DepthChanAft(u16),
-    DepthPolyAft(u16),
-    FreqCC(u8),
+    Depth(i16),
+    DepthCC(i16),
+    DepthChanAft(i16),
+    DepthPolyAft(i16),
+    FreqCC(f32),
     FreqChanAft(f32),
     FreqPolyAft(f32),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum FilterParameter {
     FilType(String),
     Cutoff(f32),
-    CutoffCC(u16),
-    CutoffChanAft(u16),
-    CutoffPolyAft(u16),
+    CutoffCC(i16),
+    CutoffChanAft(i16),
+    CutoffPolyAft(i16),
     Resonance(f32),
     KeyTrack(u16),
     KeyCenter(u8),
-    VelTrack(u16),
+    VelTrack(i16),
     Random(u16),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 
 pub enum FilterEGParameter {
     Delay(f32),
@@ -269,29 +317,29 @@ pub enum FilterEGParameter {
     Decay(f32),
     Sustain(f32),
     Release(f32),
-    Depth(u16),
+    Depth(i16),
     Vel2Delay(f32),
     Vel2Attack(f32),
     Vel2Hold(f32),
     Vel2Decay(f32),
     Vel2Sustain(f32),
     Vel2Release(f32),
-    Vel2Depth(u16),
+    Vel2Depth(i16),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum FilterLFOParameter {
     Delay(f32),
     Fade(f32),
     Freq(f32),
-    Depth(u16),
-    DepthCC(u16),
-    DepthChanAft(u16),
-    DepthPolyAft(u16),
-    FreqCC(u8),
+    Depth(i16),
+    DepthCC(i16),
+    DepthChanAft(i16),
+    DepthPolyAft(i16),
+    FreqCC(f32),
     FreqChanAft(f32),
     FreqPolyAft(f32),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AmplifierParameter {
     AmpKeyCenter(u8),
     AmpKeyTrack(f32),
@@ -300,12 +348,12 @@ pub enum AmplifierParameter {
     AmpVelCurve(f32),
     AmplVelTrackCurveCC(u8),
     AmplVelTrackCurveOnCC(u8),
-    AmpVelTrackRandom(String),
+    AmpVelTrackRandom(f32),
     AmpVelTrack(f32),
     AmplitudeCC(f32),
     AmplitudeCurveCC(u8),
     AmplitudeOnCC(f32),
-    AmplitudeSmoothCC(String),
+    AmplitudeSmoothCC(f32),
     Amplitude(f32),
     GainOnCC(f32),
     GainCC(f32),
@@ -316,38 +364,38 @@ pub enum AmplifierParameter {
     GroupVolume(f32),
     MasterAmplitude(f32),
     MasterVolume(f32),
-    PanCC(String),
-    PanCurveCC(f32),
+    PanCC(f32),
+    PanCurveCC(u32),
     PanKeyCenter(u8),
     PanKeyTrack(f32),
     PanLaw(String),
-    PanOnCC(String),
+    PanOnCC(f32),
     PanRandom(f32),
-    PanSmoothCC(String),
-    PanStepCC(String),
+    PanSmoothCC(f32),
+    PanStepCC(f32),
     PanVelTrack(f32),
     Pan(f32),
     Phase(String),
     PositionCurveCC(u32),
-    PositionKeyCenter(String),
-    PositionKeyTrack(String),
-    PositionOnCC(String),
+    PositionKeyCenter(u8),
+    PositionKeyTrack(f32),
+    PositionOnCC(f32),
     PositionRandom(f32),
-    PositionSmoothCC(String),
-    PositionStepCC(String),
-    PositionVelTrack(String),
+    PositionSmoothCC(f32),
+    PositionStepCC(f32),
+    PositionVelTrack(f32),
     Position(f32),
     RtDecay(f32),
     RtDecayNTime(f32),
     RTDecayN(f32),
     VolumeCurveCC(u32),
     VolumeOnCC(f32),
-    VolumeSmoothCC(String),
-    VolumeStepCC(String),
+    VolumeSmoothCC(f32),
+    VolumeStepCC(f32),
     Volume(f32),
     WidthCurveCC(u32),
-    WidthOnCC(String),
-    WidthStepCC(String),
+    WidthOnCC(f32),
+    WidthStepCC(f32),
     Width(f32),
     CfInLowKey(u8),
     CfInHighKey(u8),
@@ -365,7 +413,7 @@ pub enum AmplifierParameter {
     CfOutHighCC(u8),
     CfCCCurve(String),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AmplifierEGParameter {
     Delay(f32),
     Start(f32),
@@ -379,7 +427,7 @@ pub enum AmplifierEGParameter {
     Vel2Hold(f32),
     Vel2Decay(f32),
     Vel2Sustain(f32),
-    Vel2Release,
+    Vel2Release(f32),
     DelayCC(f32),
     StartCC(f32),
     AttackCC(f32),
@@ -388,20 +436,20 @@ pub enum AmplifierEGParameter {
     SustainCC(f32),
     ReleaseCC(f32),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AmplifierLFOParameter {
     Delay(f32),
     Fade(f32),
     Freq(f32),
-    Depth(u16),
-    DepthCC(u16),
-    DepthChanAft(u16),
-    DepthPolyAft(u16),
-    FreqCC(u8),
+    Depth(f32),
+    DepthCC(f32),
+    DepthChanAft(f32),
+    DepthPolyAft(f32),
+    FreqCC(f32),
     FreqChanAft(f32),
     FreqPolyAft(f32),
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 
 pub enum EqualizerParameter {
     Eq1Freq(f32),
@@ -430,7 +478,7 @@ pub enum EqualizerParameter {
     Eq3Vel2Gain(f32),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AriaEffect {
     Limiter,
     Overdrive,
@@ -447,7 +495,7 @@ pub enum AriaEffect {
     SubSynth,
     RezFilter,
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum SfzEffect {
     Apan,
     Comp,
@@ -465,7 +513,7 @@ pub enum SfzEffect {
     Strings,
     Tdfir,
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum EffectType {
     // These variants are going to have to be
     // refactored as this is a confusing naming.
@@ -473,12 +521,99 @@ pub enum EffectType {
     SfzEffects(SfzEffect),
 }
 
+impl EffectType {
+    /// Maps the value of an `<effect>` header's `type` opcode to an effect.
+    /// ARIA's bundled effects are named after their plugin identifiers,
+    /// e.g. `com.mda.Limiter`.
+    pub fn from_name(name: &str) -> Option<EffectType> {
+        let aria_effect = match name {
+            "com.mda.Limiter" => Some(AriaEffect::Limiter),
+            "com.mda.Overdrive" => Some(AriaEffect::Overdrive),
+            "com.mda.Leslie" => Some(AriaEffect::Leslie),
+            "com.mda.RingMod" => Some(AriaEffect::RingMod),
+            "com.mda.Delay" => Some(AriaEffect::Delay),
+            "com.mda.Bandisto" => Some(AriaEffect::Bandisto),
+            "com.mda.Ambience" => Some(AriaEffect::Ambience),
+            "com.mda.DubDelay" => Some(AriaEffect::DubDelay),
+            "com.mda.Detune" => Some(AriaEffect::Detune),
+            "com.mda.Dither" => Some(AriaEffect::Dither),
+            "com.mda.Combo" => Some(AriaEffect::Combo),
+            "com.mda.Degrade" => Some(AriaEffect::Degrade),
+            "com.mda.SubSynth" => Some(AriaEffect::SubSynth),
+            "com.mda.RezFilter" => Some(AriaEffect::RezFilter),
+            _ => None,
+        };
+        if let Some(aria_effect) = aria_effect {
+            return Some(EffectType::AriaEffects(aria_effect));
+        }
+
+        let sfz_effect = match name {
+            "apan" => SfzEffect::Apan,
+            "comp" => SfzEffect::Comp,
+            "delay" => SfzEffect::Delay,
+            "disto" => SfzEffect::Disto,
+            "eq" => SfzEffect::Eq,
+            "filter" => SfzEffect::Filter,
+            "fverb" => SfzEffect::Fverb,
+            "gate" => SfzEffect::Gate,
+            "limiter" => SfzEffect::Limiter,
+            "lofi" => SfzEffect::Lofi,
+            "mverb" => SfzEffect::Mverb,
+            "phaser" => SfzEffect::Phaser,
+            "static" => SfzEffect::Static,
+            "strings" => SfzEffect::Strings,
+            "tdfir" => SfzEffect::Tdfir,
+            _ => return None,
+        };
+        Some(EffectType::SfzEffects(sfz_effect))
+    }
+
+    /// The value of the `type` opcode naming the effect.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectType::AriaEffects(aria_effect) => match aria_effect {
+                AriaEffect::Limiter => "com.mda.Limiter",
+                AriaEffect::Overdrive => "com.mda.Overdrive",
+                AriaEffect::Leslie => "com.mda.Leslie",
+                AriaEffect::RingMod => "com.mda.RingMod",
+                AriaEffect::Delay => "com.mda.Delay",
+                AriaEffect::Bandisto => "com.mda.Bandisto",
+                AriaEffect::Ambience => "com.mda.Ambience",
+                AriaEffect::DubDelay => "com.mda.DubDelay",
+                AriaEffect::Detune => "com.mda.Detune",
+                AriaEffect::Dither => "com.mda.Dither",
+                AriaEffect::Combo => "com.mda.Combo",
+                AriaEffect::Degrade => "com.mda.Degrade",
+                AriaEffect::SubSynth => "com.mda.SubSynth",
+                AriaEffect::RezFilter => "com.mda.RezFilter",
+            },
+            EffectType::SfzEffects(sfz_effect) => match sfz_effect {
+                SfzEffect::Apan => "apan",
+                SfzEffect::Comp => "comp",
+                SfzEffect::Delay => "delay",
+                SfzEffect::Disto => "disto",
+                SfzEffect::Eq => "eq",
+                SfzEffect::Filter => "filter",
+                SfzEffect::Fverb => "fverb",
+                SfzEffect::Gate => "gate",
+                SfzEffect::Limiter => "limiter",
+                SfzEffect::Lofi => "lofi",
+                SfzEffect::Mverb => "mverb",
+                SfzEffect::Phaser => "phaser",
+                SfzEffect::Static => "static",
+                SfzEffect::Strings => "strings",
+                SfzEffect::Tdfir => "tdfir",
+            },
+        }
+    }
+}
+
 // Performance parameters are all sound modifiers including:
 // Pitch
 // Amplifier
 // Filter
 // EQ
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PerformanceParameter {
     SamplePlayer(SamplePlayerParameter),
     Pitch(PitchParameter),
@@ -493,6 +628,7 @@ pub enum PerformanceParameter {
     Equalizer(EqualizerParameter),
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct DefineDirective {
     define_name: String,
     define_value: String,
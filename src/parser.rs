@@ -1,29 +1,164 @@
+use crate::comment::{comment_len, comment_start, find_comment};
+use crate::header_types::HeaderType;
+use crate::registry::{lookup, ValueType};
 use nom::{
-    bytes::complete::{tag, take_until1, take_while, take_while1}, sequence::tuple, IResult
+    bytes::complete::{tag, take_until1, take_while, take_while1},
+    error::{Error, ErrorKind},
+    multi::many0,
+    sequence::{delimited, tuple},
+    Err, IResult,
 };
 
 pub fn parse_identifier(sfz_source: &str) -> IResult<&str, &str> {
-    let (remaining, _) = take_while(|c: char| c.is_whitespace())(sfz_source)?;
-    take_while(|c: char| c.is_alphanumeric() || c == '_')(remaining)
+    let (remaining, _) = white_space(sfz_source)?;
+    take_while1(|c: char| c.is_alphanumeric() || c == '_')(remaining)
+}
 
+/// Parses a value up to whitespace, `=` or a header glued to it, as in
+/// `lokey=1<region>`. Free text values are parsed with `parse_free_text_value`.
+pub fn parse_value(sfz_source: &str) -> IResult<&str, &str> {
+    let (remaining, value) = take_while1(|c: char| !c.is_whitespace() && c != '=' && c != '<')(sfz_source)?;
+    // A comment may directly follow a value, as in `lokey=36// kick`.
+    match find_comment(value) {
+        Some((0, _)) => Err(Err::Error(Error::new(sfz_source, ErrorKind::TakeWhile1))),
+        Some((comment, _)) => Ok((&sfz_source[comment..], &value[..comment])),
+        None => Ok((remaining, value)),
+    }
 }
 
+/// Whether an opcode's value is free text, such as a sample path or a label,
+/// which may contain spaces. Labels the registry doesn't know are free text too.
+pub fn is_free_text_opcode(key: &str) -> bool {
+    match lookup(key) {
+        Some(info) => info.value_type == ValueType::FreeText,
+        None => key.ends_with("_label"),
+    }
+}
 
-pub fn parse_value(sfz_source: &str) -> IResult<&str, &str> {
-    take_while1(|c: char| !c.is_whitespace() && c != '=')(sfz_source)
+/// Whether `sfz_source` starts with something that ends a free text value:
+/// another `opcode=`, a header, a directive or a comment.
+fn ends_free_text(sfz_source: &str) -> bool {
+    let key_len = sfz_source
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(sfz_source.len());
+    (key_len > 0 && sfz_source[key_len..].starts_with('='))
+        || sfz_source.starts_with('<')
+        || sfz_source.starts_with("#define")
+        || sfz_source.starts_with("#include")
+        || comment_start(sfz_source).is_some()
+}
+
+/// Parses a free text value, which runs until the end of the line or until
+/// whatever follows a space ends it, as in `sample=Piano C4 soft.wav lokey=60`.
+pub fn parse_free_text_value(sfz_source: &str) -> IResult<&str, &str> {
+    let line_end = sfz_source.find(['\r', '\n']).unwrap_or(sfz_source.len());
+    let line = &sfz_source[..line_end];
+    let mut end = find_comment(line).map_or(line_end, |(comment, _)| comment);
+
+    for (index, c) in line.char_indices() {
+        if index >= end {
+            break;
+        }
+        let next = index + c.len_utf8();
+        if c.is_whitespace() && ends_free_text(line[next..].trim_start()) {
+            end = index;
+            break;
+        }
+    }
+
+    let value = line[..end].trim_end();
+    if value.is_empty() {
+        return Err(Err::Error(Error::new(sfz_source, ErrorKind::TakeWhile1)));
+    }
+    Ok((&sfz_source[value.len()..], value))
 }
 
 pub fn parse_key_value(sfz_source: &str) -> IResult<&str, (&str, &str)> {
-    let (remaining, (key, _, value)) = tuple((parse_identifier, tag("="), parse_value))(sfz_source)?;
+    let (remaining, (key, _)) = tuple((parse_identifier, tag("=")))(sfz_source)?;
+    let (remaining, value) = if is_free_text_opcode(key) {
+        parse_free_text_value(remaining)?
+    } else {
+        parse_value(remaining)?
+    };
     Ok((remaining, (key, value)))
 }
 
+/// Parses every `key=value` pair up to the next header or directive.
+pub fn parse_opcodes(sfz_source: &str) -> IResult<&str, Vec<(&str, &str)>> {
+    many0(parse_key_value)(sfz_source)
+}
+
+/// Parses a header such as `<region>`, skipping any whitespace before it.
+pub fn parse_header(sfz_source: &str) -> IResult<&str, HeaderType> {
+    let (header_start, _) = white_space(sfz_source)?;
+    let (remaining, name) = delimited(
+        tag("<"),
+        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+        tag(">"),
+    )(header_start)?;
+
+    match HeaderType::from_name(name) {
+        Some(header_type) => Ok((remaining, header_type)),
+        None => Err(Err::Error(Error::new(header_start, ErrorKind::Tag))),
+    }
+}
+
 pub fn take_to_newline(sfz_source: &str) -> IResult<&str, &str> {
     let (remaining, output) = take_until1("\n")(sfz_source)?;
     Ok((remaining, output))
 }
+/// Skips whitespace along with any `//` and `/* */` comments.
 pub fn white_space(sfz_source: &str) -> IResult<&str, ()> {
-   let (remaining, _) = take_while(|c: char| c.is_whitespace())(sfz_source)?;
+   let mut remaining = sfz_source;
+   loop {
+       (remaining, _) = take_while(|c: char| c.is_whitespace())(remaining)?;
+       match comment_start(remaining) {
+           Some(kind) => remaining = &remaining[comment_len(remaining, kind)..],
+           None => break,
+       }
+   }
 
    Ok((remaining, ()))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_opcodes_without_a_key() {
+        assert!(parse_identifier("=5").is_err());
+        assert!(parse_key_value("=5 sample=a.wav").is_err());
+    }
+
+    #[test]
+    fn values_end_at_a_header() {
+        assert_eq!(parse_key_value("lokey=1<region>"), Ok(("<region>", ("lokey", "1"))));
+        assert!(parse_key_value("lokey=<region>").is_err());
+    }
+
+    #[test]
+    fn values_end_at_a_comment() {
+        assert_eq!(parse_key_value("lokey=36// kick"), Ok(("// kick", ("lokey", "36"))));
+    }
+
+    #[test]
+    fn free_text_values_keep_spaces() {
+        assert_eq!(
+            parse_key_value("sample=Piano C4 soft.wav lokey=60"),
+            Ok((" lokey=60", ("sample", "Piano C4 soft.wav")))
+        );
+        assert_eq!(parse_key_value("sample=a b.wav <region>"), Ok((" <region>", ("sample", "a b.wav"))));
+    }
+
+    #[test]
+    fn parses_known_headers_only() {
+        assert_eq!(parse_header("  <region>"), Ok(("", HeaderType::Region)));
+        assert!(parse_header("<regoin>").is_err());
+    }
+
+    #[test]
+    fn skips_comments_with_white_space() {
+        assert_eq!(white_space(" // one\n/* two */ <group>"), Ok(("<group>", ())));
+    }
+}
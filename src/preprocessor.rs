@@ -0,0 +1,541 @@
+use crate::comment::{strip_comments, Comment, StrippedSource};
+use crate::control::{parse_define_directive, parse_include_directive, variable_name};
+use crate::error::{SfzError, SfzErrorKind};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+/// How deeply `#include` directives may nest before loading is abandoned.
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Supplies the contents of SFZ files, so that includes can be served
+/// from somewhere other than the file system.
+pub trait FileLoader {
+    fn load(&self, path: &Path) -> io::Result<String>;
+}
+
+/// Loads files from disk.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FsLoader;
+
+impl FileLoader for FsLoader {
+    fn load(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Serves files from an in-memory map keyed by their normalized path.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    pub files: HashMap<PathBuf, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        MemoryLoader::default()
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, sfz_source: impl Into<String>) {
+        self.files.insert(normalize_path(path.as_ref()), sfz_source.into());
+    }
+}
+
+impl FileLoader for MemoryLoader {
+    fn load(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(&normalize_path(path))
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.display().to_string()))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// A run of preprocessed text and where it came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Segment {
+    start: usize,
+    len: usize,
+    file: usize,
+    file_offset: usize,
+    /// Whether the text was copied unchanged, so offsets within it map
+    /// one to one. Text the preprocessor inserted maps to `file_offset`.
+    verbatim: bool,
+}
+
+/// A position in one of the files that made up the preprocessed text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceLocation {
+    /// Index into `SourceMap::files`.
+    pub file: usize,
+    /// Byte offset into that file's text.
+    pub offset: usize,
+}
+
+/// Maps byte offsets in preprocessed text back to the files it was
+/// assembled from.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    pub files: Vec<SourceFile>,
+    segments: Vec<Segment>,
+}
+
+impl SourceMap {
+    pub fn locate(&self, offset: usize) -> Option<SourceLocation> {
+        let index = self.segments.partition_point(|segment| segment.start <= offset);
+        // An offset at the very end of the text belongs to the last segment.
+        let segment = self.segments.get(index.checked_sub(1)?)?;
+        let delta = if segment.verbatim { (offset - segment.start).min(segment.len) } else { 0 };
+        Some(SourceLocation { file: segment.file, offset: segment.file_offset + delta })
+    }
+
+    /// The 1-based line and column, counted in characters, of a location.
+    pub fn line_column(&self, location: SourceLocation) -> (usize, usize) {
+        line_column(&self.files[location.file].text, location.offset)
+    }
+
+    fn add_file(&mut self, path: PathBuf, text: String) -> usize {
+        self.files.push(SourceFile { path, text });
+        self.files.len() - 1
+    }
+
+    fn push(&mut self, output: &mut String, text: &str, file: usize, file_offset: usize, verbatim: bool) {
+        if text.is_empty() {
+            return;
+        }
+        self.segments.push(Segment { start: output.len(), len: text.len(), file, file_offset, verbatim });
+        output.push_str(text);
+    }
+}
+
+/// The 1-based line and column, counted in characters, of a byte offset.
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// A `#define` directive, in the order it was encountered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Define {
+    /// The variable name, without its leading `$`.
+    pub name: String,
+    pub value: String,
+    /// Where the directive starts.
+    pub location: SourceLocation,
+    /// How many times the variable was substituted while this
+    /// definition was in effect.
+    pub uses: usize,
+}
+
+/// A `$variable` used while no definition for it was in effect.
+/// The text is left as written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndefinedVariable {
+    /// The variable name, without its leading `$`.
+    pub name: String,
+    pub location: SourceLocation,
+}
+
+/// The output of the preprocessor: a single document with every include
+/// expanded in place and every defined variable substituted, and the map
+/// back to the original files.
+#[derive(Clone, Debug)]
+pub struct Preprocessed {
+    pub text: String,
+    pub source_map: SourceMap,
+    /// Every `#define`, including redefinitions, in document order.
+    pub defines: Vec<Define>,
+    pub undefined_variables: Vec<UndefinedVariable>,
+    /// Only filled in when the preprocessor was asked to retain comments.
+    pub comments: Vec<SourceComment>,
+    /// Warnings about undefined variables and, when preprocessing in
+    /// tolerant mode, errors for the includes that were skipped.
+    pub diagnostics: Vec<SfzError>,
+}
+
+/// A comment removed from one of the preprocessed files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceComment {
+    /// Index into `SourceMap::files`.
+    pub file: usize,
+    pub comment: Comment,
+}
+
+/// Expands `#include` and `#define` directives in document order, the way
+/// sfizz and ARIA do, blanking out comments while keeping every offset
+/// in place.
+///
+/// Included paths are resolved relative to the directory of the file
+/// including them, with backslashes treated as path separators.
+/// A `#define` applies to all text following it, including included files,
+/// header names, opcode names, values and include paths. Redefining a
+/// variable only affects the text after the redefinition. Directives are
+/// kept in the output, with included text directly following its `#include`.
+pub struct Preprocessor<L> {
+    loader: L,
+    max_depth: usize,
+    retain_comments: bool,
+}
+
+impl<L: FileLoader> Preprocessor<L> {
+    pub fn new(loader: L) -> Self {
+        Preprocessor { loader, max_depth: DEFAULT_MAX_INCLUDE_DEPTH, retain_comments: false }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Keeps the comments removed from every file in `Preprocessed::comments`,
+    /// for tools that re-emit them.
+    pub fn with_comments(mut self, retain_comments: bool) -> Self {
+        self.retain_comments = retain_comments;
+        self
+    }
+
+    /// Loads the file at `path` through the loader and expands it.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Preprocessed, SfzError> {
+        self.load_with(path.as_ref(), false)
+    }
+
+    /// Loads and expands the file at `path` like `load`, but skips includes
+    /// which can't be expanded, recording why in `Preprocessed::diagnostics`.
+    pub fn load_tolerant(&self, path: impl AsRef<Path>) -> Result<Preprocessed, SfzError> {
+        self.load_with(path.as_ref(), true)
+    }
+
+    /// Expands source that has already been read, as if it was loaded from `path`.
+    pub fn preprocess(&self, path: impl AsRef<Path>, sfz_source: impl Into<String>) -> Result<Preprocessed, SfzError> {
+        self.preprocess_with(path.as_ref(), sfz_source.into(), false)
+    }
+
+    /// Expands source that has already been read like `preprocess`, but skips
+    /// includes which can't be expanded.
+    pub fn preprocess_tolerant(&self, path: impl AsRef<Path>, sfz_source: impl Into<String>) -> Preprocessed {
+        self.preprocess_with(path.as_ref(), sfz_source.into(), true)
+            .expect("tolerant preprocessing records errors instead of returning them")
+    }
+
+    fn load_with(&self, path: &Path, tolerant: bool) -> Result<Preprocessed, SfzError> {
+        let path = normalize_path(path);
+        let sfz_source = self.loader.load(&path).map_err(|error| {
+            let message = format!("could not load {}: {}", path.display(), error);
+            SfzError::new(SfzErrorKind::Io, message, "", 0..0).in_file(&path)
+        })?;
+        self.preprocess_with(&path, sfz_source, tolerant)
+    }
+
+    fn preprocess_with(&self, path: &Path, sfz_source: String, tolerant: bool) -> Result<Preprocessed, SfzError> {
+        let path = normalize_path(path);
+        let mut expansion = Expansion {
+            text: String::new(),
+            source_map: SourceMap::default(),
+            stack: vec![path.clone()],
+            defines: vec![],
+            active_defines: HashMap::new(),
+            undefined_variables: vec![],
+            comments: vec![],
+            tolerant,
+            diagnostics: vec![],
+        };
+        let root = expansion.source_map.add_file(path, sfz_source);
+        self.expand(&mut expansion, root)?;
+
+        Ok(Preprocessed {
+            text: expansion.text,
+            source_map: expansion.source_map,
+            defines: expansion.defines,
+            undefined_variables: expansion.undefined_variables,
+            comments: expansion.comments,
+            diagnostics: expansion.diagnostics,
+        })
+    }
+
+    fn expand(&self, expansion: &mut Expansion, file: usize) -> Result<(), SfzError> {
+        let SourceFile { path, text } = expansion.source_map.files[file].clone();
+        // Directives and variables inside comments are ignored, and
+        // comments are left out of the output.
+        let StrippedSource { text, comments } = strip_comments(&text);
+        if self.retain_comments {
+            expansion
+                .comments
+                .extend(comments.into_iter().map(|comment| SourceComment { file, comment }));
+        }
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut copied = 0;
+        let mut position = 0;
+
+        while let Some(found) = text[position..].find(['#', '$']) {
+            let start = position + found;
+            let input = &text[start..];
+            position = start + 1;
+
+            if let Ok((rest, (name, value))) = parse_define_directive(input) {
+                position = text.len() - rest.len();
+                expansion.define(name, value, SourceLocation { file, offset: start });
+            } else if let Ok((rest, include)) = parse_include_directive(input) {
+                let end = text.len() - rest.len();
+                expansion.push_verbatim(&text[copied..end], file, copied);
+                copied = end;
+                position = end;
+
+                let include = expansion.substitute(include, SourceLocation { file, offset: start });
+                if let Err(error) = self.include(expansion, directory, &include, file, start..end) {
+                    if !expansion.tolerant {
+                        return Err(error);
+                    }
+                    expansion.diagnostics.push(error);
+                }
+            } else if let Ok((_, name)) = variable_name(input) {
+                let location = SourceLocation { file, offset: start };
+                let Some((matched, value)) = expansion.lookup(name) else {
+                    expansion.undefined(name, location);
+                    continue;
+                };
+                expansion.push_verbatim(&text[copied..start], file, copied);
+                expansion.push_inserted(&value, file, start);
+                copied = start + 1 + matched;
+                position = copied;
+            }
+        }
+        expansion.push_verbatim(&text[copied..], file, copied);
+
+        Ok(())
+    }
+
+    /// Loads and expands the file included by the directive at `directive`
+    /// in `file`, placing it right after the directive.
+    fn include(&self, expansion: &mut Expansion, directory: &Path, include: &str, file: usize, directive: Range<usize>) -> Result<(), SfzError> {
+        let SourceFile { path, text } = &expansion.source_map.files[file];
+        let include_error = |kind, message: String| {
+            SfzError::new(kind, message, text, directive.clone())
+                .in_file(path)
+                .with_opcode("#include")
+        };
+
+        let include_path = normalize_path(&directory.join(include.replace('\\', "/")));
+        if expansion.stack.contains(&include_path) {
+            let mut chain: Vec<String> = expansion.stack.iter().map(|path| path.display().to_string()).collect();
+            chain.push(include_path.display().to_string());
+            let message = format!("include cycle: {}", chain.join(" -> "));
+            return Err(include_error(SfzErrorKind::IncludeCycle, message));
+        }
+        if expansion.stack.len() > self.max_depth {
+            let message = format!(
+                "including {} exceeds the maximum include depth of {}",
+                include_path.display(),
+                self.max_depth
+            );
+            return Err(include_error(SfzErrorKind::IncludeDepth, message));
+        }
+        let included = self.loader.load(&include_path).map_err(|error| {
+            let message = format!("could not load {}: {}", include_path.display(), error);
+            include_error(SfzErrorKind::Io, message)
+        })?;
+
+        // Keep the included text apart from whatever surrounds the directive.
+        let location = SourceLocation { file, offset: directive.end };
+        expansion.push_inserted("\n", location.file, location.offset);
+        let included_file = expansion.source_map.add_file(include_path.clone(), included);
+        expansion.stack.push(include_path);
+        self.expand(expansion, included_file)?;
+        expansion.stack.pop();
+        expansion.push_inserted("\n", location.file, location.offset);
+
+        Ok(())
+    }
+}
+
+struct Expansion {
+    text: String,
+    source_map: SourceMap,
+    /// The chain of files currently being expanded, used to detect cycles.
+    stack: Vec<PathBuf>,
+    defines: Vec<Define>,
+    /// Maps each variable name to its current definition in `defines`.
+    active_defines: HashMap<String, usize>,
+    undefined_variables: Vec<UndefinedVariable>,
+    comments: Vec<SourceComment>,
+    /// Whether include errors are recorded in `diagnostics` instead of
+    /// ending the expansion.
+    tolerant: bool,
+    diagnostics: Vec<SfzError>,
+}
+
+impl Expansion {
+    fn undefined(&mut self, name: &str, location: SourceLocation) {
+        let SourceFile { path, text } = &self.source_map.files[location.file];
+        let span = location.offset..location.offset + 1 + name.len();
+        let warning = SfzError::new(SfzErrorKind::UndefinedVariable, format!("undefined variable `${name}`"), text, span)
+            .in_file(path)
+            .as_warning();
+        self.diagnostics.push(warning);
+        self.undefined_variables.push(UndefinedVariable { name: name.to_owned(), location });
+    }
+
+    fn push_verbatim(&mut self, text: &str, file: usize, file_offset: usize) {
+        self.source_map.push(&mut self.text, text, file, file_offset, true);
+    }
+
+    fn push_inserted(&mut self, text: &str, file: usize, file_offset: usize) {
+        self.source_map.push(&mut self.text, text, file, file_offset, false);
+    }
+
+    fn define(&mut self, name: &str, value: &str, location: SourceLocation) {
+        self.active_defines.insert(name.to_owned(), self.defines.len());
+        self.defines.push(Define { name: name.to_owned(), value: value.to_owned(), location, uses: 0 });
+    }
+
+    /// Finds the definition for the identifier following a `$`, returning
+    /// how many of its characters the variable name covers, and the value.
+    /// As in ARIA, when the whole identifier isn't defined the longest
+    /// defined prefix is used, so `$KEYa` expands `$KEY` if only that exists.
+    fn lookup(&mut self, identifier: &str) -> Option<(usize, String)> {
+        let matched = identifier
+            .char_indices()
+            .map(|(index, c)| index + c.len_utf8())
+            .rev()
+            .find(|len| self.active_defines.contains_key(&identifier[..*len]))?;
+        let define = &mut self.defines[self.active_defines[&identifier[..matched]]];
+        define.uses += 1;
+        Some((matched, define.value.clone()))
+    }
+
+    /// Substitutes variables in text that is used by the preprocessor itself,
+    /// such as an include path.
+    fn substitute(&mut self, text: &str, location: SourceLocation) -> String {
+        let mut substituted = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('$') {
+            substituted.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let lookup = variable_name(rest).ok().map(|(_, name)| (name, self.lookup(name)));
+            match lookup {
+                Some((_, Some((matched, value)))) => {
+                    substituted.push_str(&value);
+                    rest = &rest[1 + matched..];
+                }
+                Some((name, None)) => {
+                    self.undefined(name, location);
+                    substituted.push('$');
+                    rest = &rest[1..];
+                }
+                None => {
+                    substituted.push('$');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        substituted.push_str(rest);
+        substituted
+    }
+}
+
+/// Normalizes `.` and `..` components without touching the file system,
+/// so that loaders which aren't backed by disk see consistent paths.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocessor() -> Preprocessor<MemoryLoader> {
+        let mut loader = MemoryLoader::new();
+        loader.insert(
+            "dir/main.sfz",
+            "#define $KEY 60\n<region> key=$KEY // c4\n#include \"sub\\inc.sfz\"\n<region> key=$KEY $UNDEF=1\n",
+        );
+        loader.insert("dir/sub/inc.sfz", "#define $KEY 62\n<region> key=$KEY\n");
+        loader.insert("a.sfz", "#include \"b.sfz\"\n");
+        loader.insert("b.sfz", "#include \"a.sfz\"\n");
+        Preprocessor::new(loader)
+    }
+
+    #[test]
+    fn expands_includes_and_defines_in_document_order() {
+        let preprocessed = preprocessor().load("dir/main.sfz").unwrap();
+        assert_eq!(
+            preprocessed.text,
+            "#define $KEY 60\n<region> key=60      \n#include \"sub\\inc.sfz\"\n\
+             #define $KEY 62\n<region> key=62\n\n\n<region> key=62 $UNDEF=1\n"
+        );
+        let uses: Vec<_> = preprocessed.defines.iter().map(|define| (define.value.as_str(), define.uses)).collect();
+        assert_eq!(uses, [("60", 1), ("62", 2)]);
+        assert_eq!(preprocessed.undefined_variables[0].name, "UNDEF");
+        assert_eq!(preprocessed.diagnostics[0].kind, SfzErrorKind::UndefinedVariable);
+    }
+
+    #[test]
+    fn maps_offsets_back_to_the_file_they_came_from() {
+        let preprocessed = preprocessor().load("dir/main.sfz").unwrap();
+        let locate = |needle: &str| {
+            let location = preprocessed.source_map.locate(preprocessed.text.find(needle).unwrap()).unwrap();
+            let file = &preprocessed.source_map.files[location.file].path;
+            (file.to_str().unwrap().to_owned(), preprocessed.source_map.line_column(location))
+        };
+        assert_eq!(locate("key=60"), ("dir/main.sfz".to_owned(), (2, 10)));
+        assert_eq!(locate("key=62"), ("dir/sub/inc.sfz".to_owned(), (2, 10)));
+        assert_eq!(locate("<region> key=62 $"), ("dir/main.sfz".to_owned(), (4, 1)));
+        assert_eq!(locate("$UNDEF"), ("dir/main.sfz".to_owned(), (4, 19)));
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let error = preprocessor().load("a.sfz").unwrap_err();
+        assert_eq!(error.kind, SfzErrorKind::IncludeCycle);
+        assert_eq!(error.to_string(), "b.sfz:1:1: error: include cycle: a.sfz -> b.sfz -> a.sfz");
+
+        let preprocessed = preprocessor().load_tolerant("a.sfz").unwrap();
+        assert_eq!(preprocessed.text, "#include \"b.sfz\"\n#include \"a.sfz\"\n\n\n");
+        assert_eq!(preprocessed.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn retains_comments_with_the_file_they_came_from() {
+        let comments = |preprocessor: Preprocessor<MemoryLoader>| {
+            let preprocessed = preprocessor.load("dir/main.sfz").unwrap();
+            let files = preprocessed.source_map.files;
+            let comments = preprocessed.comments.into_iter();
+            comments.map(|comment| (files[comment.file].path.clone(), comment.comment.text)).collect::<Vec<_>>()
+        };
+        assert_eq!(comments(preprocessor()), []);
+        assert_eq!(comments(preprocessor().with_comments(true)), [(PathBuf::from("dir/main.sfz"), "// c4".to_owned())]);
+    }
+
+    #[test]
+    fn limits_include_depth() {
+        let error = preprocessor().with_max_depth(0).load("dir/main.sfz").unwrap_err();
+        assert_eq!(error.kind, SfzErrorKind::IncludeDepth);
+        assert_eq!((error.line, error.column), (3, 1));
+    }
+
+    #[test]
+    fn reports_missing_includes() {
+        let error = preprocessor().preprocess("main.sfz", "<region>\n#include \"missing.sfz\"\n").unwrap_err();
+        assert_eq!((error.kind, error.line), (SfzErrorKind::Io, 2));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::header_types::{Control, HeaderType};
+use crate::instrument::{ParseOptions, SfzInstrument};
+use crate::note::MiddleC;
+use crate::registry::{lookup, lookup_alias, SfzVersion};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// An SFZ player, and how it reads instruments. Players differ in the
+/// opcodes and headers they accept, the octave they write middle C in,
+/// how `default_path` carries over between control headers, and where
+/// samples may live.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlayerProfile {
+    Aria,
+    Sfizz,
+    Cakewalk,
+    LinuxSampler,
+    BassMidi,
+}
+
+pub const ALL_PLAYERS: [PlayerProfile; 5] = [
+    PlayerProfile::Aria,
+    PlayerProfile::Sfizz,
+    PlayerProfile::Cakewalk,
+    PlayerProfile::LinuxSampler,
+    PlayerProfile::BassMidi,
+];
+
+impl PlayerProfile {
+    pub fn from_name(name: &str) -> Option<PlayerProfile> {
+        ALL_PLAYERS.iter().copied().find(|player| player.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerProfile::Aria => "aria",
+            PlayerProfile::Sfizz => "sfizz",
+            PlayerProfile::Cakewalk => "cakewalk",
+            PlayerProfile::LinuxSampler => "linuxsampler",
+            PlayerProfile::BassMidi => "bassmidi",
+        }
+    }
+
+    /// The versions of the registry whose opcodes and spellings the player reads.
+    fn versions(&self) -> &'static [SfzVersion] {
+        match self {
+            PlayerProfile::Aria => &[SfzVersion::V1, SfzVersion::V2, SfzVersion::Aria, SfzVersion::Cakewalk],
+            PlayerProfile::Sfizz => &[
+                SfzVersion::V1,
+                SfzVersion::V2,
+                SfzVersion::Aria,
+                SfzVersion::Cakewalk,
+                SfzVersion::Sfizz,
+            ],
+            PlayerProfile::Cakewalk => &[SfzVersion::V1, SfzVersion::Cakewalk],
+            PlayerProfile::LinuxSampler => &[SfzVersion::V1, SfzVersion::V2],
+            PlayerProfile::BassMidi => &[SfzVersion::V1],
+        }
+    }
+
+    /// Opcodes the player reads from versions it otherwise doesn't, by
+    /// their name in the registry.
+    fn extra_opcodes(&self) -> &'static [&'static str] {
+        match self {
+            PlayerProfile::Cakewalk => &["default_path"],
+            PlayerProfile::BassMidi => &["default_path", "note_offset", "octave_offset", "set_ccN"],
+            _ => &[],
+        }
+    }
+
+    pub fn supports_version(&self, version: SfzVersion) -> bool {
+        self.versions().contains(&version)
+    }
+
+    pub fn supports_header(&self, header: HeaderType) -> bool {
+        match self {
+            PlayerProfile::Aria => header != HeaderType::Sample,
+            PlayerProfile::Sfizz => header != HeaderType::Midi,
+            PlayerProfile::Cakewalk => matches!(
+                header,
+                HeaderType::Control | HeaderType::Global | HeaderType::Group | HeaderType::Region
+            ),
+            PlayerProfile::LinuxSampler => !matches!(header, HeaderType::Effect | HeaderType::Midi | HeaderType::Sample),
+            PlayerProfile::BassMidi => matches!(
+                header,
+                HeaderType::Control | HeaderType::Global | HeaderType::Master | HeaderType::Group | HeaderType::Region
+            ),
+        }
+    }
+
+    /// Whether the player expands the directive `name`, such as `#include`.
+    /// Cakewalk predates both `#define` and `#include`.
+    pub fn supports_directive(&self, name: &str) -> bool {
+        matches!(name, "#define" | "#include") && *self != PlayerProfile::Cakewalk
+    }
+
+    /// Whether the player reads the opcode `key`, as written. Aliases must
+    /// be read by the player too. Opcodes missing from the registry are
+    /// assumed to be supported, since nothing is known about them.
+    pub fn supports_opcode(&self, key: &str) -> bool {
+        if lookup_alias(key).is_some_and(|alias| !self.supports_version(alias.version)) {
+            return false;
+        }
+        match lookup(key) {
+            Some(info) => self.supports_version(info.version) || self.extra_opcodes().contains(&info.name),
+            None => true,
+        }
+    }
+
+    /// The octave the player writes middle C in.
+    pub fn middle_c(&self) -> MiddleC {
+        match self {
+            PlayerProfile::Cakewalk => MiddleC::C3,
+            _ => MiddleC::C4,
+        }
+    }
+
+    /// Whether a new control header resets `default_path`, as in ARIA,
+    /// or the last one set stays in effect, as in Cakewalk.
+    pub fn resets_default_path(&self) -> bool {
+        matches!(self, PlayerProfile::Aria | PlayerProfile::Sfizz)
+    }
+
+    /// Whether `default_path` and `sample` may be absolute. Cakewalk only
+    /// loads samples relative to the instrument.
+    pub fn allows_absolute_paths(&self) -> bool {
+        *self != PlayerProfile::Cakewalk
+    }
+
+    /// Options to parse with as the player would, skipping the opcodes and
+    /// headers it doesn't read.
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions { middle_c: self.middle_c(), player: Some(*self), ..ParseOptions::default() }
+    }
+
+    /// The `default_path` in effect for headers under the control header
+    /// at index `control` of `controls`.
+    pub fn default_path<'a>(&self, controls: &'a [Control], control: Option<usize>) -> &'a Path {
+        let Some(control) = control else {
+            return Path::new("");
+        };
+        if self.resets_default_path() {
+            return &controls[control].default_path;
+        }
+        controls[..=control]
+            .iter()
+            .rev()
+            .map(|control| control.default_path.as_path())
+            .find(|path| !path.as_os_str().is_empty())
+            .unwrap_or(Path::new(""))
+    }
+
+    /// Where the player looks for `sample` below `default_path`. Both may
+    /// use `\` as a separator, as files written on Windows do. `None` when
+    /// the player doesn't allow an absolute path that is given.
+    pub fn sample_path(&self, default_path: &Path, sample: &str) -> Option<PathBuf> {
+        let default_path = PathBuf::from(default_path.to_string_lossy().replace('\\', "/"));
+        let sample = PathBuf::from(sample.replace('\\', "/"));
+        if !self.allows_absolute_paths() && (default_path.is_absolute() || sample.is_absolute()) {
+            return None;
+        }
+        Some(default_path.join(sample))
+    }
+
+    /// The headers used in `instrument` that the player ignores.
+    pub fn unsupported_headers(&self, instrument: &SfzInstrument<String>) -> Vec<HeaderType> {
+        let used = [
+            (HeaderType::Control, !instrument.control.is_empty()),
+            (HeaderType::Global, !instrument.global.is_empty()),
+            (HeaderType::Master, instrument.master.as_ref().is_some_and(|masters| !masters.is_empty())),
+            (HeaderType::Group, !instrument.group.is_empty()),
+            (HeaderType::Region, !instrument.region.is_empty()),
+            (HeaderType::Curve, !instrument.curve.is_empty()),
+            (HeaderType::Effect, !instrument.effect.is_empty()),
+            (HeaderType::Midi, !instrument.midi.is_empty()),
+            (HeaderType::Sample, !instrument.sample.is_empty()),
+        ];
+        used.into_iter()
+            .filter(|(header, used)| *used && !self.supports_header(*header))
+            .map(|(header, _)| header)
+            .collect()
+    }
+
+    /// The opcodes used in `instrument` that the player ignores, spelled
+    /// as they were written, in alphabetical order.
+    pub fn unsupported_opcodes(&self, instrument: &SfzInstrument<String>) -> Vec<String> {
+        let mut unsupported = BTreeSet::new();
+        let mut check = |key: &str, spellings: Option<&HashMap<String, String>>| {
+            let key = spellings.and_then(|spellings| spellings.get(key)).map_or(key, String::as_str);
+            if !self.supports_opcode(key) {
+                unsupported.insert(key.to_owned());
+            }
+        };
+
+        for control in &instrument.control {
+            if !control.default_path.as_os_str().is_empty() {
+                check("default_path", None);
+            }
+            if control.note_offset != 0 {
+                check("note_offset", None);
+            }
+            if control.octave_offset != 0 {
+                check("octave_offset", None);
+            }
+            if control.sw_note_offset != 0 {
+                check("sw_note_offset", None);
+            }
+            if control.sw_octave_offset != 0 {
+                check("sw_octave_offset", None);
+            }
+            control
+                .label_ccn
+                .keys()
+                .map(|cc| format!("label_cc{cc}"))
+                .chain(control.label_keyn.keys().map(|key| format!("label_key{key}")))
+                .chain(control.label_outputn.keys().map(|output| format!("label_output{output}")))
+                .for_each(|key| check(&key, None));
+            control
+                .set_ccn
+                .keys()
+                .chain(control.set_hdccn.keys())
+                .chain(control.set_realccn.keys())
+                .chain(control.hints.keys())
+                .for_each(|key| check(key, None));
+        }
+        for global in &instrument.global {
+            global.common_params.keys().for_each(|key| check(key, Some(&global.spellings)));
+        }
+        for master in instrument.master.iter().flatten() {
+            master.op_codes.iter().for_each(|(key, _)| check(key, Some(&master.spellings)));
+        }
+        for group in &instrument.group {
+            group.common_params.keys().for_each(|key| check(key, Some(&group.spellings)));
+        }
+        for region in &instrument.region {
+            region.parameters.keys().for_each(|key| check(key, Some(&region.spellings)));
+        }
+        for curve in &instrument.curve {
+            curve.values.iter().for_each(|(key, _)| check(key, None));
+        }
+        unsupported.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::parse_sfz_with;
+
+    #[test]
+    fn supports_the_opcodes_of_its_versions() {
+        assert!(PlayerProfile::Aria.supports_opcode("sample_fadeout"));
+        assert!(!PlayerProfile::BassMidi.supports_opcode("sample_fadeout"));
+        assert!(PlayerProfile::Aria.supports_opcode("label_cc7"));
+        assert!(!PlayerProfile::LinuxSampler.supports_opcode("label_cc7"));
+        assert!(PlayerProfile::BassMidi.supports_opcode("note_offset"));
+        assert!(!PlayerProfile::Cakewalk.supports_opcode("note_offset"));
+        assert!(PlayerProfile::Cakewalk.supports_opcode("default_path"));
+        assert!(PlayerProfile::BassMidi.supports_opcode("made_up_opcode"));
+    }
+
+    #[test]
+    fn supports_aliases_the_player_reads() {
+        assert!(PlayerProfile::Cakewalk.supports_opcode("loopmode"));
+        assert!(!PlayerProfile::LinuxSampler.supports_opcode("loopmode"));
+        assert!(PlayerProfile::LinuxSampler.supports_opcode("loop_mode"));
+        assert!(PlayerProfile::Aria.supports_opcode("polyphony_group"));
+        assert!(!PlayerProfile::Cakewalk.supports_opcode("polyphony_group"));
+    }
+
+    #[test]
+    fn supports_headers() {
+        assert!(!PlayerProfile::Aria.supports_header(HeaderType::Sample));
+        assert!(!PlayerProfile::Sfizz.supports_header(HeaderType::Midi));
+        assert!(PlayerProfile::Sfizz.supports_header(HeaderType::Sample));
+        assert!(PlayerProfile::Cakewalk.supports_header(HeaderType::Region));
+        assert!(!PlayerProfile::Cakewalk.supports_header(HeaderType::Master));
+        assert!(PlayerProfile::BassMidi.supports_header(HeaderType::Master));
+        assert!(!PlayerProfile::BassMidi.supports_header(HeaderType::Curve));
+        assert!(!PlayerProfile::LinuxSampler.supports_header(HeaderType::Effect));
+    }
+
+    #[test]
+    fn resets_default_path_per_player() {
+        let mut controls = vec![Control::new(), Control::new()];
+        controls[0].default_path = PathBuf::from("samples/");
+        assert_eq!(PlayerProfile::Aria.default_path(&controls, Some(1)), Path::new(""));
+        assert_eq!(PlayerProfile::Sfizz.default_path(&controls, Some(1)), Path::new(""));
+        assert_eq!(PlayerProfile::Cakewalk.default_path(&controls, Some(1)), Path::new("samples/"));
+        assert_eq!(PlayerProfile::BassMidi.default_path(&controls, Some(1)), Path::new("samples/"));
+        assert_eq!(PlayerProfile::Aria.default_path(&controls, Some(0)), Path::new("samples/"));
+        assert_eq!(PlayerProfile::Cakewalk.default_path(&controls, None), Path::new(""));
+    }
+
+    #[test]
+    fn parses_note_names_with_the_players_middle_c() {
+        let sfz_source = "<region> lokey=c4 hikey=c5 sample=a.wav";
+        let region = &parse_sfz_with(sfz_source, &PlayerProfile::Cakewalk.parse_options()).unwrap().region[0];
+        assert_eq!((region.low_key, region.high_key, region.pitch_keycenter), (72, 84, 60));
+        let region = &parse_sfz_with(sfz_source, &PlayerProfile::Aria.parse_options()).unwrap().region[0];
+        assert_eq!((region.low_key, region.high_key), (60, 72));
+    }
+}
@@ -1,7 +1,7 @@
 use refinement::{Predicate, Refinement};
 
-// https://docs.rs/nom/latest/nom/combinator/fn.verify.html
-// This might come in handy for applying our refinements.
+// Applied to opcode values through `nom::combinator::verify`, see
+// `validation::refined`.
 pub struct ZeroToSixteen;
 pub type RangeZeroToSixteen = Refinement<u8, ZeroToSixteen>;
 impl Predicate<u8> for ZeroToSixteen {
@@ -10,72 +10,192 @@ impl Predicate<u8> for ZeroToSixteen {
     }
 }
 
+pub struct OneToSixteen;
+pub type RangeOneToSixteen = Refinement<u8, OneToSixteen>;
+impl Predicate<u8> for OneToSixteen {
+    fn test(x: &u8) -> bool {
+        (1..=16).contains(x)
+    }
+}
+
 pub struct ZeroToOneTwentySeven;
 pub type RangeZeroToOneTwentySeven = Refinement<u8, ZeroToOneTwentySeven>;
 impl Predicate<u8> for ZeroToOneTwentySeven {
     fn test(x: &u8) -> bool {
-        *x <= 16
+        *x <= 127
+    }
+}
+
+pub struct OneTwentySevenNegToPos;
+pub type RangeOneTwentySevenNegToPos = Refinement<i8, OneTwentySevenNegToPos>;
+impl Predicate<i8> for OneTwentySevenNegToPos {
+    fn test(x: &i8) -> bool {
+        (-127..=127).contains(x)
+    }
+}
+
+pub struct TenNegToPos;
+pub type RangeTenNegToPos = Refinement<i8, TenNegToPos>;
+impl Predicate<i8> for TenNegToPos {
+    fn test(x: &i8) -> bool {
+        (-10..=10).contains(x)
     }
 }
 
 pub struct EightThousandOneNinetyTwoNegToPos;
-type RangeEightThousandOneNinetyTwoNegToPos = Refinement<u16, EightThousandOneNinetyTwoNegToPos>;
+pub type RangeEightThousandOneNinetyTwoNegToPos = Refinement<i16, EightThousandOneNinetyTwoNegToPos>;
 impl Predicate<i16> for EightThousandOneNinetyTwoNegToPos {
     fn test(x: &i16) -> bool {
-        -8192 <= *x && *x >= 8192
+        (-8192..=8192).contains(x)
+    }
+}
+
+pub struct TwelveHundredNegToPos;
+pub type RangeTwelveHundredNegToPos = Refinement<i16, TwelveHundredNegToPos>;
+impl Predicate<i16> for TwelveHundredNegToPos {
+    fn test(x: &i16) -> bool {
+        (-1200..=1200).contains(x)
+    }
+}
+
+pub struct NinetySixHundredNegToPos;
+pub type RangeNinetySixHundredNegToPos = Refinement<i16, NinetySixHundredNegToPos>;
+impl Predicate<i16> for NinetySixHundredNegToPos {
+    fn test(x: &i16) -> bool {
+        (-9600..=9600).contains(x)
     }
 }
 
 pub struct FloatZeroToOne;
-type RangeFloatZeroToOne = Refinement<f32, FloatZeroToOne>;
+pub type RangeFloatZeroToOne = Refinement<f32, FloatZeroToOne>;
 impl Predicate<f32> for FloatZeroToOne {
     fn test(x: &f32) -> bool {
-        0.0 <= *x && *x >= 1.0
+        (0.0..=1.0).contains(x)
+    }
+}
+
+pub struct FloatZeroToForty;
+pub type RangeFloatZeroToForty = Refinement<f32, FloatZeroToForty>;
+impl Predicate<f32> for FloatZeroToForty {
+    fn test(x: &f32) -> bool {
+        (0.0..=40.0).contains(x)
     }
 }
 
 pub struct FloatZeroToFiveHundred;
-type RangeFloatZeroToFiveHundredw = Refinement<f32, FloatZeroToFiveHundred>;
+pub type RangeFloatZeroToFiveHundred = Refinement<f32, FloatZeroToFiveHundred>;
 impl Predicate<f32> for FloatZeroToFiveHundred {
     fn test(x: &f32) -> bool {
-        0.0 <= *x && *x >= 500.0
+        (0.0..=500.0).contains(x)
     }
 }
 
 pub struct OneToOneHundred;
-type RangeOneToOneHundred = Refinement<u8, OneToOneHundred>;
+pub type RangeOneToOneHundred = Refinement<u8, OneToOneHundred>;
 impl Predicate<u8> for OneToOneHundred {
     fn test(x: &u8) -> bool {
-        *x <= 100
+        (1..=100).contains(x)
     }
 }
+
 pub struct ZeroToU32BitMax;
-type RangeZeroToU32BitMax = Refinement<u32, ZeroToU32BitMax>;
+pub type RangeZeroToU32BitMax = Refinement<u32, ZeroToU32BitMax>;
 impl Predicate<u32> for ZeroToU32BitMax {
-    fn test(x: &u32) -> bool {
-        *x <= u32::MAX
+    fn test(_: &u32) -> bool {
+        // Every u32 is in range, the refinement only documents intent.
+        true
     }
 }
+
 pub struct FloatZeroToOneHundred;
-type RangeFloatZeroToOneHundred = Refinement<f32, FloatZeroToOneHundred>;
+pub type RangeFloatZeroToOneHundred = Refinement<f32, FloatZeroToOneHundred>;
 impl Predicate<f32> for FloatZeroToOneHundred {
     fn test(x: &f32) -> bool {
-        0.0 <= *x && *x >= 100.0
+        (0.0..=100.0).contains(x)
+    }
+}
+
+pub struct FloatOneHundredNegToPos;
+pub type RangeFloatOneHundredNegToPos = Refinement<f32, FloatOneHundredNegToPos>;
+impl Predicate<f32> for FloatOneHundredNegToPos {
+    fn test(x: &f32) -> bool {
+        (-100.0..=100.0).contains(x)
+    }
+}
+
+pub struct FloatNegOneFortyFourToSix;
+pub type RangeFloatNegOneFortyFourToSix = Refinement<f32, FloatNegOneFortyFourToSix>;
+impl Predicate<f32> for FloatNegOneFortyFourToSix {
+    fn test(x: &f32) -> bool {
+        (-144.0..=6.0).contains(x)
     }
 }
 
+// An i64 holds both -1 and the whole u32 range.
 pub struct NegOneToU32BitMax;
-type RangeNegOneToU32BitMax = Refinement<u32, NegOneToU32BitMax>;
-impl Predicate<i32> for NegOneToU32BitMax {
-    fn test(x: &i32) -> bool {
-        -1 <= *x && *x >= i32::MAX
+pub type RangeNegOneToU32BitMax = Refinement<i64, NegOneToU32BitMax>;
+impl Predicate<i64> for NegOneToU32BitMax {
+    fn test(x: &i64) -> bool {
+        (-1..=i64::from(u32::MAX)).contains(x)
     }
 }
 
 pub struct FloatZeroToThirtyTwo;
-type RangeFloatZeroToThirtyTwo = Refinement<f32, FloatZeroToThirtyTwo>;
+pub type RangeFloatZeroToThirtyTwo = Refinement<f32, FloatZeroToThirtyTwo>;
 impl Predicate<f32> for FloatZeroToThirtyTwo {
     fn test(x: &f32) -> bool {
-        0.0 <= *x && *x >= 32.0
+        (0.0..=32.0).contains(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsigned_ranges_include_both_bounds() {
+        assert!(ZeroToSixteen::test(&0) && ZeroToSixteen::test(&16) && !ZeroToSixteen::test(&17));
+        assert!(!OneToSixteen::test(&0) && OneToSixteen::test(&1) && OneToSixteen::test(&16));
+        assert!(!OneToSixteen::test(&17));
+        assert!(ZeroToOneTwentySeven::test(&0) && ZeroToOneTwentySeven::test(&127));
+        assert!(!ZeroToOneTwentySeven::test(&128));
+        assert!(!OneToOneHundred::test(&0) && OneToOneHundred::test(&1) && OneToOneHundred::test(&100));
+        assert!(!OneToOneHundred::test(&101));
+        assert!(ZeroToU32BitMax::test(&0) && ZeroToU32BitMax::test(&u32::MAX));
+    }
+
+    #[test]
+    fn signed_ranges_include_both_bounds() {
+        assert!(!OneTwentySevenNegToPos::test(&-128));
+        assert!(OneTwentySevenNegToPos::test(&-127) && OneTwentySevenNegToPos::test(&127));
+        assert!(!TenNegToPos::test(&-11) && TenNegToPos::test(&-10) && TenNegToPos::test(&10));
+        assert!(!TenNegToPos::test(&11));
+        assert!(!EightThousandOneNinetyTwoNegToPos::test(&-8193));
+        assert!(EightThousandOneNinetyTwoNegToPos::test(&-8192) && EightThousandOneNinetyTwoNegToPos::test(&8192));
+        assert!(!EightThousandOneNinetyTwoNegToPos::test(&8193));
+        assert!(!TwelveHundredNegToPos::test(&-1201));
+        assert!(TwelveHundredNegToPos::test(&-1200) && TwelveHundredNegToPos::test(&1200));
+        assert!(!TwelveHundredNegToPos::test(&1201));
+        assert!(!NinetySixHundredNegToPos::test(&-9601));
+        assert!(NinetySixHundredNegToPos::test(&-9600) && NinetySixHundredNegToPos::test(&9600));
+        assert!(!NinetySixHundredNegToPos::test(&9601));
+        let u32_max = i64::from(u32::MAX);
+        assert!(!NegOneToU32BitMax::test(&-2) && NegOneToU32BitMax::test(&-1) && NegOneToU32BitMax::test(&u32_max));
+        assert!(!NegOneToU32BitMax::test(&(u32_max + 1)));
+    }
+
+    fn includes_both_bounds<P: Predicate<f32>>(min: f32, max: f32) -> bool {
+        P::test(&min) && P::test(&max) && !P::test(&(min - 0.01)) && !P::test(&(max + 0.01)) && !P::test(&f32::NAN)
+    }
+
+    #[test]
+    fn float_ranges_include_both_bounds() {
+        assert!(includes_both_bounds::<FloatZeroToOne>(0.0, 1.0));
+        assert!(includes_both_bounds::<FloatZeroToForty>(0.0, 40.0));
+        assert!(includes_both_bounds::<FloatZeroToFiveHundred>(0.0, 500.0));
+        assert!(includes_both_bounds::<FloatZeroToOneHundred>(0.0, 100.0));
+        assert!(includes_both_bounds::<FloatOneHundredNegToPos>(-100.0, 100.0));
+        assert!(includes_both_bounds::<FloatNegOneFortyFourToSix>(-144.0, 6.0));
+        assert!(includes_both_bounds::<FloatZeroToThirtyTwo>(0.0, 32.0));
     }
 }
@@ -1,29 +1,126 @@
+use crate::header_types::HeaderScope;
+use crate::note::MiddleC;
+use crate::opcode::{parse_opcode_with, typed_opcodes, Opcode};
+use crate::opcode_key::OpcodeKey;
+use crate::opcode_types::{
+    AmplifierParameter, InstrumentSettings, KeyMapping, PerformanceParameter, PitchParameter, SamplePlayback,
+};
 use crate::parser::parse_key_value;
 use nom::{bytes::complete::tag, character::complete::space0, multi::many0, IResult};
 use std::collections::HashMap;
+use std::ops::Range;
 #[derive(Debug, PartialEq, Clone)]
 pub struct Region {
-    low_velocity: u32,
-    high_velocity: u32,
-    volume: f32,
-    region_label: String,
-    sample: (String, String),
-    offset: u32,
-    parameters: HashMap<String, String>,
+    /// `lokey`, or `key`; 0 unless set.
+    pub low_key: u8,
+    /// `hikey`, or `key`; 127 unless set.
+    pub high_key: u8,
+    /// `lovel`; 1 unless set.
+    pub low_velocity: u8,
+    /// `hivel`; 127 unless set.
+    pub high_velocity: u8,
+    /// `pitch_keycenter`, or `key`; 60 unless set.
+    pub pitch_keycenter: u8,
+    /// `volume` in dB; 0 unless set.
+    pub volume: f32,
+    pub region_label: String,
+    /// `sample` as written, relative to the instrument's `default_path`.
+    pub sample: String,
+    /// `offset` in samples; 0 unless set.
+    pub offset: u32,
+    pub parameters: HashMap<String, String>,
+    /// The headers enclosing this region.
+    pub scope: HeaderScope,
+    /// Opcodes written with an alias, from their canonical name to the spelling used.
+    pub spellings: HashMap<String, String>,
+    /// Byte range of the `<region>` header in the text the region was
+    /// parsed from; empty for regions made in code.
+    pub span: Range<usize>,
 }
 
-pub struct SFZFile {
-    elements: Vec<Region>,
+impl Region {
+    /// Creates a region whose fields are derived from `parameters`,
+    /// with the spec's defaults for opcodes that are missing or invalid.
+    /// Note names in key opcodes are read with `middle_c`.
+    pub fn new(parameters: HashMap<String, String>, middle_c: MiddleC) -> Self {
+        let mut region = Region {
+            low_key: 0,
+            high_key: 127,
+            low_velocity: 1,
+            high_velocity: 127,
+            pitch_keycenter: 60,
+            volume: 0f32,
+            region_label: String::new(),
+            sample: String::new(),
+            offset: 0,
+            parameters: HashMap::new(),
+            scope: HeaderScope::default(),
+            spellings: HashMap::new(),
+            span: 0..0,
+        };
+        region.apply_opcodes(&parameters, middle_c);
+        region.parameters = parameters;
+        region
+    }
+
+    /// Updates the fields from the opcodes of one header. `key` is applied
+    /// first, so `lokey`, `hikey` and `pitch_keycenter` in the same header
+    /// override it. Note names are read with `middle_c`.
+    pub fn apply_opcodes<'a>(
+        &mut self,
+        opcodes: impl IntoIterator<Item = (&'a String, &'a String)>,
+        middle_c: MiddleC,
+    ) {
+        let mut opcodes: Vec<(&String, &String)> = opcodes.into_iter().collect();
+        opcodes.sort_by_key(|(key, _)| key.as_str() != "key");
+
+        // Velocities kept out of range under `RangePolicy::Report` still
+        // parse as `u8`, so they are held to the MIDI range here. Keys
+        // outside of it don't parse as notes at all.
+        for (key, value) in opcodes {
+            match parse_opcode_with(key, value, middle_c) {
+                Ok(Opcode::KeyMapping(KeyMapping::Key(key))) => {
+                    self.low_key = key;
+                    self.high_key = key;
+                    self.pitch_keycenter = key;
+                }
+                Ok(Opcode::KeyMapping(KeyMapping::LoKey(key))) => self.low_key = key,
+                Ok(Opcode::KeyMapping(KeyMapping::HiKey(key))) => self.high_key = key,
+                Ok(Opcode::KeyMapping(KeyMapping::LoVel(velocity))) => self.low_velocity = velocity.min(127),
+                Ok(Opcode::KeyMapping(KeyMapping::HiVel(velocity))) => self.high_velocity = velocity.min(127),
+                Ok(Opcode::Performance(PerformanceParameter::Pitch(PitchParameter::KeyCenter(key)))) => {
+                    self.pitch_keycenter = key
+                }
+                Ok(Opcode::Performance(PerformanceParameter::Amplifier(AmplifierParameter::Volume(volume)))) => {
+                    self.volume = volume
+                }
+                Ok(Opcode::InstrumentSettings(InstrumentSettings::RegionLabel(label))) => self.region_label = label,
+                Ok(Opcode::SamplePlayback(SamplePlayback::Sample(sample))) => self.sample = sample,
+                Ok(Opcode::SamplePlayback(SamplePlayback::Offset(offset))) => self.offset = offset,
+                _ => {}
+            }
+        }
+    }
+
+    /// Sets one of the region's own opcodes, updating the fields derived
+    /// from it. Opcodes are what gets written back out, so generators
+    /// should set them rather than the fields.
+    pub fn set_opcode(&mut self, key: impl Into<String>, value: impl Into<String>, middle_c: MiddleC) {
+        let (key, value) = (key.into(), value.into());
+        self.apply_opcodes([(&key, &value)], middle_c);
+        self.parameters.insert(key, value);
+    }
+
+    /// The region's own opcodes as typed opcodes, ordered by key.
+    pub fn typed_opcodes(&self) -> Vec<(OpcodeKey, Opcode)> {
+        typed_opcodes(&self.parameters)
+    }
 }
 
 pub fn parse_region(sfz_source: &str) -> IResult<&str, Region> {
     let (remaining, _) = tag("<region>")(sfz_source)?;
     let (remaining, _) = space0(remaining)?;
-    let (remaining, params) = nom::multi::many0(parse_key_value)(remaining)?;
-
-    for i in &params {
-        println!("i is: {:?}", *i);
-    }
+    let (remaining, params) = many0(parse_key_value)(remaining)?;
 
     let mut parameters = HashMap::new();
 
@@ -34,18 +131,31 @@ pub fn parse_region(sfz_source: &str) -> IResult<&str, Region> {
         parameters.insert(key.to_string(), value.to_string());
     }
 
-    Ok((sfz_source, Region { 
-        parameters,
-        low_velocity: 0,
-        high_velocity: 0,
-        volume: 0f32,
-        region_label: String::new(),
-        sample: (String::new(), String::new()),
-        offset: 0
-    }))
+    Ok((remaining, Region::new(parameters, MiddleC::default())))
 }
 
-pub fn parse_sfz(sfz_source: &str) -> IResult<&str, SFZFile> {
-    let (remaining, elements) = many0(parse_region)(sfz_source)?;
-    Ok((remaining, SFZFile { elements }))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_parameters(parameters: &[(&str, &str)]) -> HashMap<String, String> {
+        parameters.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn keeps_keys_and_velocities_in_the_midi_range() {
+        let parameters = [("lovel", "200"), ("hivel", "255"), ("key", "a9")];
+        let region = Region::new(to_parameters(&parameters), MiddleC::C4);
+        assert_eq!((region.low_velocity, region.high_velocity), (127, 127));
+        assert_eq!((region.low_key, region.high_key, region.pitch_keycenter), (0, 127, 60));
+    }
+
+    #[test]
+    fn reads_note_names_with_the_given_middle_c() {
+        let parameters = [("lokey", "c3"), ("hikey", "c4"), ("pitch_keycenter", "c3")];
+        let region = Region::new(to_parameters(&parameters), MiddleC::C3);
+        assert_eq!((region.low_key, region.high_key, region.pitch_keycenter), (60, 72, 60));
+        let region = Region::new(to_parameters(&parameters), MiddleC::C4);
+        assert_eq!((region.low_key, region.high_key, region.pitch_keycenter), (48, 60, 48));
+    }
 }
@@ -0,0 +1,280 @@
+use crate::header_types::HeaderType;
+use crate::validation::ValueRange;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// The specification, or the player, an opcode was introduced by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SfzVersion {
+    V1,
+    V2,
+    Aria,
+    Cakewalk,
+    Sfizz,
+}
+
+impl SfzVersion {
+    pub fn from_name(name: &str) -> Option<SfzVersion> {
+        match name {
+            "v1" => Some(SfzVersion::V1),
+            "v2" => Some(SfzVersion::V2),
+            "aria" => Some(SfzVersion::Aria),
+            "cakewalk" => Some(SfzVersion::Cakewalk),
+            "sfizz" => Some(SfzVersion::Sfizz),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SfzVersion::V1 => "v1",
+            SfzVersion::V2 => "v2",
+            SfzVersion::Aria => "aria",
+            SfzVersion::Cakewalk => "cakewalk",
+            SfzVersion::Sfizz => "sfizz",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueType {
+    Integer,
+    Float,
+    /// A MIDI note number or a note name such as `c#4`.
+    Note,
+    /// A single word, such as `loop_continuous`.
+    Text,
+    /// Text running up to the next opcode, which may contain spaces,
+    /// such as sample paths and labels.
+    FreeText,
+}
+
+impl ValueType {
+    pub fn from_name(name: &str) -> Option<ValueType> {
+        match name {
+            "integer" => Some(ValueType::Integer),
+            "float" => Some(ValueType::Float),
+            "note" => Some(ValueType::Note),
+            "text" => Some(ValueType::Text),
+            "free_text" => Some(ValueType::FreeText),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::Integer => "integer",
+            ValueType::Float => "float",
+            ValueType::Note => "note",
+            ValueType::Text => "text",
+            ValueType::FreeText => "free_text",
+        }
+    }
+}
+
+/// Everything known about an opcode, read from `opcodes.tsv`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpcodeInfo {
+    /// The opcode's name, with `N` standing for a number as in `loccN`.
+    pub name: &'static str,
+    pub version: SfzVersion,
+    pub value_type: ValueType,
+    pub default: Option<&'static str>,
+    pub range: Option<ValueRange>,
+    pub unit: Option<&'static str>,
+    /// The headers the opcode may appear in.
+    pub headers: Vec<HeaderType>,
+}
+
+/// An alternative spelling of an opcode, read from `aliases.tsv`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AliasInfo {
+    /// The alternative spelling, with `N` standing for a number.
+    pub alias: &'static str,
+    /// The name of the opcode the alias means.
+    pub canonical: &'static str,
+    /// Where the alternative spelling comes from.
+    pub version: SfzVersion,
+}
+
+/// The numbers `key` has in place of each `N` in `pattern`, or `None`
+/// if `key` isn't an instance of `pattern`.
+pub fn pattern_numbers<'a>(pattern: &str, key: &'a str) -> Option<Vec<&'a str>> {
+    let mut pieces = pattern.split('N');
+    let mut rest = key.strip_prefix(pieces.next()?)?;
+    let mut numbers = vec![];
+    for piece in pieces {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        numbers.push(&rest[..digits]);
+        rest = rest[digits..].strip_prefix(piece)?;
+    }
+    rest.is_empty().then_some(numbers)
+}
+
+/// Fills each `N` in `pattern` with the next of `numbers`.
+fn fill_pattern(pattern: &str, numbers: &[&str]) -> String {
+    let mut numbers = numbers.iter();
+    pattern
+        .split('N')
+        .enumerate()
+        .fold(String::with_capacity(pattern.len()), |mut filled, (index, piece)| {
+            if index > 0 {
+                filled.push_str(numbers.next().copied().unwrap_or("N"));
+            }
+            filled + piece
+        })
+}
+
+impl OpcodeInfo {
+    /// Whether `key` is this opcode, with numbers in place of each `N`.
+    pub fn matches(&self, key: &str) -> bool {
+        pattern_numbers(self.name, key).is_some()
+    }
+
+    pub fn allows(&self, header: HeaderType) -> bool {
+        self.headers.contains(&header)
+    }
+}
+
+struct Registry {
+    opcodes: Vec<OpcodeInfo>,
+    /// Indices of the opcodes without a number in their name.
+    by_name: HashMap<&'static str, usize>,
+    aliases: Vec<AliasInfo>,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| {
+        let opcodes: Vec<OpcodeInfo> = rows(include_str!("opcodes.tsv"), "opcodes.tsv", parse_row);
+        let by_name = opcodes
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| !info.name.contains('N'))
+            .map(|(index, info)| (info.name, index))
+            .collect();
+        let aliases = rows(include_str!("aliases.tsv"), "aliases.tsv", parse_alias_row);
+        Registry { opcodes, by_name, aliases }
+    })
+}
+
+/// Parses each row of a table, skipping blank lines and `#` comments.
+fn rows<T>(table: &'static str, file: &str, parse: fn(&'static str) -> Option<T>) -> Vec<T> {
+    table
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| parse(line).unwrap_or_else(|| panic!("{file}:{}: malformed row `{line}`", index + 1)))
+        .collect()
+}
+
+fn parse_row(line: &'static str) -> Option<OpcodeInfo> {
+    let columns: Vec<&'static str> = line.split('\t').collect();
+    let [name, version, value_type, default, range, unit, headers] = columns[..] else {
+        return None;
+    };
+    let optional = |column: &'static str| (column != "-").then_some(column);
+    Some(OpcodeInfo {
+        name,
+        version: SfzVersion::from_name(version)?,
+        value_type: ValueType::from_name(value_type)?,
+        default: optional(default),
+        range: match optional(range) {
+            Some(range) => Some(ValueRange::from_name(range)?),
+            None => None,
+        },
+        unit: optional(unit),
+        headers: headers.split(',').map(HeaderType::from_name).collect::<Option<_>>()?,
+    })
+}
+
+fn parse_alias_row(line: &'static str) -> Option<AliasInfo> {
+    let columns: Vec<&'static str> = line.split('\t').collect();
+    let [alias, canonical, version] = columns[..] else {
+        return None;
+    };
+    Some(AliasInfo { alias, canonical, version: SfzVersion::from_name(version)? })
+}
+
+/// Every opcode in the registry, in the order of `opcodes.tsv`.
+pub fn opcodes() -> &'static [OpcodeInfo] {
+    &registry().opcodes
+}
+
+/// Every alias in the registry, in the order of `aliases.tsv`.
+pub fn aliases() -> &'static [AliasInfo] {
+    &registry().aliases
+}
+
+/// The alias `key` is an instance of, such as `gain_ccN` for `gain_cc7`.
+pub fn lookup_alias(key: &str) -> Option<&'static AliasInfo> {
+    registry().aliases.iter().find(|info| pattern_numbers(info.alias, key).is_some())
+}
+
+/// The canonical spelling of `key`, such as `volume_oncc7` for `gain_cc7`.
+/// Keys which aren't aliases are returned as they are.
+pub fn canonical_name(key: &str) -> Cow<'_, str> {
+    let Some(info) = lookup_alias(key) else {
+        return Cow::Borrowed(key);
+    };
+    let numbers = pattern_numbers(info.alias, key).unwrap_or_default();
+    Cow::Owned(fill_pattern(info.canonical, &numbers))
+}
+
+/// Looks up the opcode `key` is an instance of, such as `loccN` for `locc64`.
+/// Aliases are looked up under their canonical spelling.
+pub fn lookup(key: &str) -> Option<&'static OpcodeInfo> {
+    let registry = registry();
+    let key = canonical_name(key);
+    match registry.by_name.get(key.as_ref()) {
+        Some(index) => Some(&registry.opcodes[*index]),
+        None => registry.opcodes.iter().find(|info| info.name.contains('N') && info.matches(&key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn loads_both_tables() {
+        assert!(opcodes().len() > 100);
+        assert!(!aliases().is_empty());
+        assert_eq!(lookup("polyphony_stealing").map(|info| info.value_type), Some(ValueType::Integer));
+    }
+
+    #[test]
+    fn aliases_mean_opcodes_in_the_table() {
+        for alias in aliases() {
+            let known = opcodes().iter().any(|info| info.name == alias.canonical);
+            assert!(known, "`{}` means `{}`, which isn't in opcodes.tsv", alias.alias, alias.canonical);
+            assert_eq!(alias.alias.matches('N').count(), alias.canonical.matches('N').count(), "{}", alias.alias);
+        }
+    }
+
+    #[test]
+    fn canonicalizes_aliases() {
+        assert_eq!(canonical_name("gain_cc7"), "volume_oncc7");
+        assert_eq!(canonical_name("ampeg_attackcc64"), "ampeg_attack_oncc64");
+        assert_eq!(canonical_name("loopmode"), "loop_mode");
+        assert_eq!(canonical_name("polyphony_group"), "group");
+        assert!(matches!(canonical_name("volume_oncc7"), Cow::Borrowed("volume_oncc7")));
+        assert!(matches!(canonical_name("made_up_opcode"), Cow::Borrowed(_)));
+        assert_eq!(lookup_alias("on_locc64").map(|info| info.canonical), Some("start_loccN"));
+        assert_eq!(lookup("gain_cc7").map(|info| info.name), Some("volume_onccN"));
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let mut names = HashSet::new();
+        for name in opcodes().iter().map(|info| info.name).chain(aliases().iter().map(|info| info.alias)) {
+            assert!(names.insert(name), "`{name}` is listed more than once");
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use crate::note::{note_number, parse_any_note_name, MiddleC};
+use crate::refinements::{
+    EightThousandOneNinetyTwoNegToPos, FloatNegOneFortyFourToSix, FloatOneHundredNegToPos, FloatZeroToForty,
+    FloatZeroToOne, FloatZeroToOneHundred, NegOneToU32BitMax, NinetySixHundredNegToPos, OneToOneHundred,
+    OneToSixteen, OneTwentySevenNegToPos, RangeZeroToOneTwentySeven, TenNegToPos, TwelveHundredNegToPos,
+    ZeroToOneTwentySeven, ZeroToU32BitMax,
+};
+use crate::registry::lookup;
+use nom::{
+    combinator::{map_opt, map_res, rest, verify},
+    IResult,
+};
+use refinement::{Predicate, Refinement};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// What to do with a value outside of its opcode's range.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RangePolicy {
+    /// Replace the value with the nearest one in range, as players do.
+    Clamp,
+    /// Keep the value as written and report it.
+    #[default]
+    Report,
+}
+
+/// The legal values of an opcode. Each range is checked with the
+/// refinement of the same bounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueRange {
+    /// MIDI data such as velocities and CC values, 0 to 127.
+    Midi,
+    /// A MIDI note number from 0 to 127, or a note name.
+    Note,
+    /// A MIDI channel, 1 to 16.
+    Channel,
+    /// Pitch bend, -8192 to 8192.
+    Bend,
+    /// 0 to 1.
+    Unit,
+    /// 0 to 100 %.
+    Percent,
+    /// -100 to 100 %.
+    SignedPercent,
+    /// Envelope stages and delays, 0 to 100 seconds.
+    Seconds,
+    /// Volume, -144 to 6 dB.
+    Decibels,
+    /// Filter resonance, 0 to 40 dB.
+    Resonance,
+    /// A position in a sample, 0 to 4294967295 frames.
+    Frames,
+    /// A position in a sample, or -1 for none.
+    FramesOrNone,
+    /// Transposition, -127 to 127 semitones.
+    Semitones,
+    /// Transposition, -10 to 10 octaves.
+    Octaves,
+    /// Pitch, -9600 to 9600 cents.
+    Cents,
+    /// Key tracking, -1200 to 1200 cents per key.
+    CentsPerKey,
+    /// A round robin length or position, 1 to 100.
+    Sequence,
+}
+
+const ALL_RANGES: [ValueRange; 17] = [
+    ValueRange::Midi,
+    ValueRange::Note,
+    ValueRange::Channel,
+    ValueRange::Bend,
+    ValueRange::Unit,
+    ValueRange::Percent,
+    ValueRange::SignedPercent,
+    ValueRange::Seconds,
+    ValueRange::Decibels,
+    ValueRange::Resonance,
+    ValueRange::Frames,
+    ValueRange::FramesOrNone,
+    ValueRange::Semitones,
+    ValueRange::Octaves,
+    ValueRange::Cents,
+    ValueRange::CentsPerKey,
+    ValueRange::Sequence,
+];
+
+/// Why a value was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueError {
+    /// The value isn't of the opcode's type.
+    Invalid { expected: &'static str },
+    /// The value is of the right type, but outside of `min` to `max`.
+    OutOfRange { min: f64, max: f64 },
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::Invalid { expected } => write!(f, "expected {expected}"),
+            ValueError::OutOfRange { min, max } => write!(f, "expected a value from {min} to {max}"),
+        }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+/// The range declared for `key` in the opcode registry, if its values are validated.
+pub fn opcode_range(key: &str) -> Option<ValueRange> {
+    lookup(key).and_then(|info| info.range)
+}
+
+/// Checks `value` against the range declared for `key`. Returns the value
+/// to use, which differs from `value` only when it was clamped.
+pub fn validate_opcode<'a>(
+    key: &str,
+    value: &'a str,
+    policy: RangePolicy,
+    middle_c: MiddleC,
+) -> Result<Cow<'a, str>, ValueError> {
+    let Some(range) = opcode_range(key) else {
+        return Ok(Cow::Borrowed(value));
+    };
+    match range.check(value, middle_c) {
+        Ok(()) => Ok(Cow::Borrowed(value)),
+        Err(ValueError::OutOfRange { .. }) if policy == RangePolicy::Clamp => {
+            Ok(Cow::Owned(range.clamp(value, middle_c)))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Parses a whole value of type `T`, verifying it with the predicate `P`.
+pub fn refined<T: FromStr, P: Predicate<T>>(value: &str) -> IResult<&str, Refinement<T, P>> {
+    map_opt(verify(map_res(rest, str::parse::<T>), P::test), Refinement::new)(value)
+}
+
+/// Parses a whole note number or note name.
+pub fn refined_note(value: &str, middle_c: MiddleC) -> IResult<&str, RangeZeroToOneTwentySeven> {
+    map_opt(
+        verify(map_opt(rest, |note| note_number(note, middle_c)), ZeroToOneTwentySeven::test),
+        Refinement::new,
+    )(value)
+}
+
+impl ValueRange {
+    /// Maps a range's name in `opcodes.tsv` to the range.
+    pub fn from_name(name: &str) -> Option<ValueRange> {
+        ALL_RANGES.iter().copied().find(|range| range.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueRange::Midi => "midi",
+            ValueRange::Note => "note",
+            ValueRange::Channel => "channel",
+            ValueRange::Bend => "bend",
+            ValueRange::Unit => "unit",
+            ValueRange::Percent => "percent",
+            ValueRange::SignedPercent => "signed_percent",
+            ValueRange::Seconds => "seconds",
+            ValueRange::Decibels => "decibels",
+            ValueRange::Resonance => "resonance",
+            ValueRange::Frames => "frames",
+            ValueRange::FramesOrNone => "frames_or_none",
+            ValueRange::Semitones => "semitones",
+            ValueRange::Octaves => "octaves",
+            ValueRange::Cents => "cents",
+            ValueRange::CentsPerKey => "cents_per_key",
+            ValueRange::Sequence => "sequence",
+        }
+    }
+
+    /// The smallest and largest legal values.
+    pub fn bounds(&self) -> (f64, f64) {
+        match self {
+            ValueRange::Midi | ValueRange::Note => (0.0, 127.0),
+            ValueRange::Channel => (1.0, 16.0),
+            ValueRange::Bend => (-8192.0, 8192.0),
+            ValueRange::Unit => (0.0, 1.0),
+            ValueRange::Percent | ValueRange::Seconds => (0.0, 100.0),
+            ValueRange::SignedPercent => (-100.0, 100.0),
+            ValueRange::Decibels => (-144.0, 6.0),
+            ValueRange::Resonance => (0.0, 40.0),
+            ValueRange::Frames => (0.0, f64::from(u32::MAX)),
+            ValueRange::FramesOrNone => (-1.0, f64::from(u32::MAX)),
+            ValueRange::Semitones => (-127.0, 127.0),
+            ValueRange::Octaves => (-10.0, 10.0),
+            ValueRange::Cents => (-9600.0, 9600.0),
+            ValueRange::CentsPerKey => (-1200.0, 1200.0),
+            ValueRange::Sequence => (1.0, 100.0),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !matches!(
+            self,
+            ValueRange::Unit
+                | ValueRange::Percent
+                | ValueRange::SignedPercent
+                | ValueRange::Seconds
+                | ValueRange::Decibels
+                | ValueRange::Resonance
+        )
+    }
+
+    fn expected(&self) -> &'static str {
+        match self {
+            ValueRange::Note => "a note number or a note name such as c#4",
+            _ if self.is_integer() => "a whole number",
+            _ => "a number",
+        }
+    }
+
+    /// The value as a number, if it is of the range's type.
+    fn number(&self, value: &str, middle_c: MiddleC) -> Option<f64> {
+        match self {
+            ValueRange::Note => value.parse::<i64>().ok().or_else(|| match parse_any_note_name(value, middle_c) {
+                Ok(("", note)) => Some(note),
+                _ => None,
+            })
+            .map(|note| note as f64),
+            _ if self.is_integer() => value.parse::<i64>().ok().map(|number| number as f64),
+            _ => value.parse::<f64>().ok().filter(|number| number.is_finite()),
+        }
+    }
+
+    pub fn check(&self, value: &str, middle_c: MiddleC) -> Result<(), ValueError> {
+        if self.number(value, middle_c).is_none() {
+            return Err(ValueError::Invalid { expected: self.expected() });
+        }
+        let in_range = match self {
+            ValueRange::Midi => refined::<u8, ZeroToOneTwentySeven>(value).is_ok(),
+            ValueRange::Note => refined_note(value, middle_c).is_ok(),
+            ValueRange::Channel => refined::<u8, OneToSixteen>(value).is_ok(),
+            ValueRange::Bend => refined::<i16, EightThousandOneNinetyTwoNegToPos>(value).is_ok(),
+            ValueRange::Unit => refined::<f32, FloatZeroToOne>(value).is_ok(),
+            ValueRange::Percent | ValueRange::Seconds => refined::<f32, FloatZeroToOneHundred>(value).is_ok(),
+            ValueRange::SignedPercent => refined::<f32, FloatOneHundredNegToPos>(value).is_ok(),
+            ValueRange::Decibels => refined::<f32, FloatNegOneFortyFourToSix>(value).is_ok(),
+            ValueRange::Resonance => refined::<f32, FloatZeroToForty>(value).is_ok(),
+            ValueRange::Frames => refined::<u32, ZeroToU32BitMax>(value).is_ok(),
+            ValueRange::FramesOrNone => refined::<i64, NegOneToU32BitMax>(value).is_ok(),
+            ValueRange::Semitones => refined::<i8, OneTwentySevenNegToPos>(value).is_ok(),
+            ValueRange::Octaves => refined::<i8, TenNegToPos>(value).is_ok(),
+            ValueRange::Cents => refined::<i16, NinetySixHundredNegToPos>(value).is_ok(),
+            ValueRange::CentsPerKey => refined::<i16, TwelveHundredNegToPos>(value).is_ok(),
+            ValueRange::Sequence => refined::<u8, OneToOneHundred>(value).is_ok(),
+        };
+        if in_range {
+            return Ok(());
+        }
+        let (min, max) = self.bounds();
+        Err(ValueError::OutOfRange { min, max })
+    }
+
+    /// The nearest legal value to `value`, which must be of the range's type.
+    /// Note names are read with `middle_c`.
+    pub fn clamp(&self, value: &str, middle_c: MiddleC) -> String {
+        let (min, max) = self.bounds();
+        let number = self.number(value, middle_c).unwrap_or(min).clamp(min, max);
+        if self.is_integer() {
+            format!("{}", number as i64)
+        } else {
+            format!("{number}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_out_of_range_note_names_as_out_of_range() {
+        let report = |value| validate_opcode("lokey", value, RangePolicy::Report, MiddleC::C4);
+        assert_eq!(report("g9"), Ok(Cow::Borrowed("g9")));
+        assert_eq!(report("a9"), Err(ValueError::OutOfRange { min: 0.0, max: 127.0 }));
+        assert_eq!(report("h4"), Err(ValueError::Invalid { expected: ValueRange::Note.expected() }));
+    }
+
+    #[test]
+    fn clamps_note_names_with_the_given_middle_c() {
+        assert_eq!(validate_opcode("hikey", "a8", RangePolicy::Clamp, MiddleC::C4), Ok(Cow::Borrowed("a8")));
+        assert_eq!(validate_opcode("hikey", "a8", RangePolicy::Clamp, MiddleC::C3), Ok(Cow::Owned("127".into())));
+        assert_eq!(validate_opcode("lovel", "200", RangePolicy::Clamp, MiddleC::C4), Ok(Cow::Owned("127".into())));
+    }
+
+    #[test]
+    fn checks_float_and_signed_ranges_at_their_bounds() {
+        assert_eq!(ValueRange::Unit.check("1", MiddleC::C4), Ok(()));
+        assert_eq!(ValueRange::Unit.check("1.01", MiddleC::C4), Err(ValueError::OutOfRange { min: 0.0, max: 1.0 }));
+        assert_eq!(ValueRange::Decibels.check("-144", MiddleC::C4), Ok(()));
+        let decibels = ValueError::OutOfRange { min: -144.0, max: 6.0 };
+        assert_eq!(ValueRange::Decibels.check("6.5", MiddleC::C4), Err(decibels));
+        assert_eq!(ValueRange::Bend.check("-8192", MiddleC::C4), Ok(()));
+        let bend = ValueError::OutOfRange { min: -8192.0, max: 8192.0 };
+        assert_eq!(ValueRange::Bend.check("8193", MiddleC::C4), Err(bend));
+        let octaves = ValueError::OutOfRange { min: -10.0, max: 10.0 };
+        assert_eq!(ValueRange::Octaves.check("-11", MiddleC::C4), Err(octaves));
+        assert_eq!(ValueRange::FramesOrNone.check("-1", MiddleC::C4), Ok(()));
+        assert_eq!(ValueRange::Bend.check("0.5", MiddleC::C4), Err(ValueError::Invalid { expected: "a whole number" }));
+        assert_eq!(ValueRange::Unit.check("inf", MiddleC::C4), Err(ValueError::Invalid { expected: "a number" }));
+    }
+
+    #[test]
+    fn clamps_float_and_signed_ranges_to_their_bounds() {
+        assert_eq!(ValueRange::Unit.clamp("1.5", MiddleC::C4), "1");
+        assert_eq!(ValueRange::Unit.clamp("0.25", MiddleC::C4), "0.25");
+        assert_eq!(ValueRange::Decibels.clamp("-200", MiddleC::C4), "-144");
+        assert_eq!(ValueRange::SignedPercent.clamp("-100.5", MiddleC::C4), "-100");
+        assert_eq!(ValueRange::Bend.clamp("-9000", MiddleC::C4), "-8192");
+        assert_eq!(ValueRange::Cents.clamp("9601", MiddleC::C4), "9600");
+        assert_eq!(ValueRange::FramesOrNone.clamp("-5", MiddleC::C4), "-1");
+    }
+}
@@ -0,0 +1,412 @@
+use crate::header_types::{Control, Curve, Effect, Global, Group, HeaderScope, HeaderType, Master, Midi, Sample};
+use crate::instrument::SfzInstrument;
+use crate::region::Region;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Write};
+
+/// How headers and their opcodes are laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WriteStyle {
+    /// The header on a line of its own, then one opcode per line, with a
+    /// blank line between headers.
+    #[default]
+    OnePerLine,
+    /// Each header on a single line, followed by its opcodes.
+    Compact,
+}
+
+/// Writes a header and its opcodes. Directives always go on lines of their own.
+fn write_header(
+    out: &mut dyn Write,
+    header_type: HeaderType,
+    opcodes: &[(String, String)],
+    directives: &[String],
+    style: WriteStyle,
+) -> fmt::Result {
+    write!(out, "<{}>", header_type.name())?;
+    let separator = match style {
+        WriteStyle::OnePerLine => '\n',
+        WriteStyle::Compact => ' ',
+    };
+    for (key, value) in opcodes {
+        write!(out, "{separator}{key}={value}")?;
+    }
+    writeln!(out)?;
+    directives.iter().try_for_each(|directive| writeln!(out, "{directive}"))
+}
+
+/// Opcodes from a map, under the spelling they were written with, in
+/// alphabetical order of their canonical names.
+fn spelled_opcodes<T: Display>(
+    opcodes: &HashMap<String, T>,
+    spellings: &HashMap<String, String>,
+) -> Vec<(String, String)> {
+    let mut keys: Vec<&String> = opcodes.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .map(|key| (spellings.get(key).unwrap_or(key).clone(), opcodes[key].to_string()))
+        .collect()
+}
+
+pub fn write_control(out: &mut dyn Write, control: &Control, style: WriteStyle) -> fmt::Result {
+    let mut opcodes = vec![];
+    if !control.default_path.as_os_str().is_empty() {
+        opcodes.push(("default_path".to_owned(), control.default_path.display().to_string()));
+    }
+    if control.note_offset != 0 {
+        opcodes.push(("note_offset".to_owned(), control.note_offset.to_string()));
+    }
+    if control.octave_offset != 0 {
+        opcodes.push(("octave_offset".to_owned(), control.octave_offset.to_string()));
+    }
+    if control.sw_note_offset != 0 {
+        opcodes.push(("sw_note_offset".to_owned(), control.sw_note_offset.to_string()));
+    }
+    if control.sw_octave_offset != 0 {
+        opcodes.push(("sw_octave_offset".to_owned(), control.sw_octave_offset.to_string()));
+    }
+    opcodes.extend(control.label_ccn.values().map(|label| (format!("label_cc{}", label.cc), label.text.clone())));
+    for opcodes_by_key in [&control.set_ccn, &control.set_hdccn, &control.set_realccn] {
+        opcodes.extend(spelled_opcodes(opcodes_by_key, &HashMap::new()));
+    }
+    opcodes.extend(control.label_keyn.values().map(|label| (format!("label_key{}", label.key), label.text.clone())));
+    opcodes.extend(
+        control.label_outputn.values().map(|label| (format!("label_output{}", label.output), label.text.clone())),
+    );
+    opcodes.extend(spelled_opcodes(&control.hints, &HashMap::new()));
+
+    let mut defines: Vec<_> = control.define_directives.iter().collect();
+    defines.sort();
+    let directives: Vec<String> = defines
+        .into_iter()
+        .map(|(name, value)| format!("#define ${name} {value}"))
+        .chain(control.include_directives.iter().map(|include| format!("#include \"{include}\"")))
+        .collect();
+    write_header(out, HeaderType::Control, &opcodes, &directives, style)
+}
+
+pub fn write_global<T: Display>(out: &mut dyn Write, global: &Global<T>, style: WriteStyle) -> fmt::Result {
+    let opcodes = spelled_opcodes(&global.common_params, &global.spellings);
+    write_header(out, HeaderType::Global, &opcodes, &[], style)
+}
+
+pub fn write_master<T: Display>(out: &mut dyn Write, master: &Master<T>, style: WriteStyle) -> fmt::Result {
+    let opcodes: Vec<(String, String)> = master
+        .op_codes
+        .iter()
+        .map(|(key, value)| (master.spellings.get(key).unwrap_or(key).clone(), value.to_string()))
+        .collect();
+    write_header(out, HeaderType::Master, &opcodes, &[], style)
+}
+
+pub fn write_group<T: Display>(out: &mut dyn Write, group: &Group<T>, style: WriteStyle) -> fmt::Result {
+    let opcodes = spelled_opcodes(&group.common_params, &group.spellings);
+    write_header(out, HeaderType::Group, &opcodes, &[], style)
+}
+
+/// Writes the region's own opcodes. Its fields are derived from them, and
+/// aren't written separately.
+pub fn write_region(out: &mut dyn Write, region: &Region, style: WriteStyle) -> fmt::Result {
+    let opcodes = spelled_opcodes(&region.parameters, &region.spellings);
+    write_header(out, HeaderType::Region, &opcodes, &[], style)
+}
+
+pub fn write_effect(out: &mut dyn Write, effect: &Effect, style: WriteStyle) -> fmt::Result {
+    let mut opcodes = vec![];
+    if let Some(effect_type) = &effect.effect_type {
+        opcodes.push(("type".to_owned(), effect_type.name().to_owned()));
+    }
+    let gains = [
+        ("effect1", effect.effect_one),
+        ("effect2", effect.effect_two),
+        ("effect3", effect.effect_three),
+        ("effect4", effect.effect_four),
+    ];
+    opcodes.extend(gains.iter().filter(|(_, gain)| *gain != 0.0).map(|(key, gain)| (key.to_string(), gain.to_string())));
+    if effect.bus != Effect::default().bus {
+        opcodes.push(("bus".to_owned(), effect.bus.name().to_owned()));
+    }
+    if effect.param_offset != 0 {
+        opcodes.push(("param_offset".to_owned(), effect.param_offset.to_string()));
+    }
+    if effect.dsp_order != 0 {
+        opcodes.push(("dsp_order".to_owned(), effect.dsp_order.to_string()));
+    }
+    opcodes.extend(spelled_opcodes(&effect.parameters, &HashMap::new()));
+    write_header(out, HeaderType::Effect, &opcodes, &[], style)
+}
+
+pub fn write_curve<T: Display>(out: &mut dyn Write, curve: &Curve<T>, style: WriteStyle) -> fmt::Result {
+    let opcodes: Vec<(String, String)> = std::iter::once(("curve_index".to_owned(), curve.index.to_string()))
+        .chain(curve.values.iter().map(|(key, value)| (key.clone(), value.to_string())))
+        .collect();
+    write_header(out, HeaderType::Curve, &opcodes, &[], style)
+}
+
+pub fn write_midi<T: Display>(out: &mut dyn Write, midi: &Midi<T>, style: WriteStyle) -> fmt::Result {
+    let opcodes: Vec<(String, String)> =
+        midi.op_codes.iter().map(|(key, value)| (key.clone(), value.to_string())).collect();
+    write_header(out, HeaderType::Midi, &opcodes, &[], style)
+}
+
+pub fn write_sample(out: &mut dyn Write, sample: &Sample, style: WriteStyle) -> fmt::Result {
+    let mut opcodes = vec![("name".to_owned(), sample.name.display().to_string())];
+    if !sample.data.is_empty() {
+        opcodes.push(("data".to_owned(), String::from_utf8_lossy(&sample.data).into_owned()));
+    }
+    write_header(out, HeaderType::Sample, &opcodes, &[], style)
+}
+
+/// Writes a whole instrument. Control, global, master and group headers
+/// are written before the first region in their scope, so regions keep
+/// the headers they inherit from. Headers no region is in come after
+/// the regions, followed by curves, effects, `<midi>` and samples.
+pub fn write_sfz<T: Display>(out: &mut dyn Write, instrument: &SfzInstrument<T>, style: WriteStyle) -> fmt::Result {
+    let mut writer = InstrumentWriter { out, instrument, style, written: Written::default(), first: true };
+    for region in &instrument.region {
+        writer.enter(region.scope)?;
+        writer.header(|out| write_region(out, region, style))?;
+    }
+    writer.enter_rest()?;
+    for curve in &instrument.curve {
+        writer.header(|out| write_curve(out, curve, style))?;
+    }
+    for effect in &instrument.effect {
+        writer.header(|out| write_effect(out, effect, style))?;
+    }
+    for midi in &instrument.midi {
+        writer.header(|out| write_midi(out, midi, style))?;
+    }
+    for sample in &instrument.sample {
+        writer.header(|out| write_sample(out, sample, style))?;
+    }
+    Ok(())
+}
+
+/// Writes a whole instrument to a string.
+pub fn to_sfz_string<T: Display>(instrument: &SfzInstrument<T>, style: WriteStyle) -> String {
+    let mut sfz_source = String::new();
+    write_sfz(&mut sfz_source, instrument, style).expect("writing to a String never fails");
+    sfz_source
+}
+
+/// How many of each scoping header have been written, and which are in effect.
+#[derive(Default)]
+struct Written {
+    controls: usize,
+    globals: usize,
+    masters: usize,
+    groups: usize,
+    scope: HeaderScope,
+}
+
+struct InstrumentWriter<'a, T> {
+    out: &'a mut dyn Write,
+    instrument: &'a SfzInstrument<T>,
+    style: WriteStyle,
+    written: Written,
+    first: bool,
+}
+
+impl<T: Display> InstrumentWriter<'_, T> {
+    /// Writes a header, separated from the one before it.
+    fn header(&mut self, write: impl FnOnce(&mut dyn Write) -> fmt::Result) -> fmt::Result {
+        if !self.first && self.style == WriteStyle::OnePerLine {
+            writeln!(self.out)?;
+        }
+        self.first = false;
+        write(self.out)
+    }
+
+    /// Writes the headers needed to put what follows in `scope`, along with
+    /// any headers before them that haven't been written.
+    fn enter(&mut self, scope: HeaderScope) -> fmt::Result {
+        let instrument = self.instrument;
+        let style = self.style;
+        if let Some(control) = scope.control.filter(|_| scope.control != self.written.scope.control) {
+            while self.written.controls <= control {
+                let index = self.written.controls;
+                self.header(|out| write_control(out, &instrument.control[index], style))?;
+                self.written.controls += 1;
+            }
+            self.written.scope.control = Some(control);
+        }
+        if let Some(global) = scope.global.filter(|_| scope.global != self.written.scope.global) {
+            while self.written.globals <= global {
+                let index = self.written.globals;
+                self.header(|out| write_global(out, &instrument.global[index], style))?;
+                self.written.globals += 1;
+            }
+            self.written.scope = HeaderScope { global: Some(global), master: None, group: None, ..self.written.scope };
+        }
+        let masters = instrument.master.as_deref().unwrap_or_default();
+        if let Some(master) = scope.master.filter(|_| scope.master != self.written.scope.master) {
+            while self.written.masters <= master {
+                let index = self.written.masters;
+                self.header(|out| write_master(out, &masters[index], style))?;
+                self.written.masters += 1;
+            }
+            self.written.scope = HeaderScope { master: Some(master), group: None, ..self.written.scope };
+        }
+        if let Some(group) = scope.group.filter(|_| scope.group != self.written.scope.group) {
+            while self.written.groups <= group {
+                let index = self.written.groups;
+                self.header(|out| write_group(out, &instrument.group[index], style))?;
+                self.written.groups += 1;
+            }
+            self.written.scope.group = Some(group);
+        }
+        Ok(())
+    }
+
+    /// Writes the scoping headers that no region was in.
+    fn enter_rest(&mut self) -> fmt::Result {
+        let instrument = self.instrument;
+        let last = |len: usize| len.checked_sub(1);
+        self.enter(HeaderScope {
+            control: last(instrument.control.len()).filter(|last| *last >= self.written.controls),
+            global: last(instrument.global.len()).filter(|last| *last >= self.written.globals),
+            master: last(instrument.master.as_ref().map_or(0, Vec::len)).filter(|last| *last >= self.written.masters),
+            group: last(instrument.group.len()).filter(|last| *last >= self.written.groups),
+        })
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_control(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl<T: Display> Display for Global<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_global(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl<T: Display> Display for Master<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_master(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl<T: Display> Display for Group<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_group(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_region(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_effect(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl<T: Display> Display for Curve<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_curve(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl<T: Display> Display for Midi<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_midi(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the header as SFZ, one opcode per line.
+impl Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_sample(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+/// Writes the instrument as SFZ, one opcode per line.
+impl<T: Display> Display for SfzInstrument<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_sfz(f, self, WriteStyle::OnePerLine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::{load_sfz, parse_sfz};
+    use crate::preprocessor::{MemoryLoader, Preprocessor};
+
+    const INSTRUMENT: &str = "<control> default_path=Samples/ label_cc1=Mod wheel (vibrato) set_cc1=64
+<global> volume=-3 loopmode=loop_continuous
+<master> pan=10
+<group> lokey=36 hikey=48 seq_length=2
+<region> sample=kick 1.wav seq_position=1
+<region> sample=kick 2.wav seq_position=2 cutoff_cc74=1200
+<group> key=60
+<region> sample=snare.wav lovel=1 hivel=64
+<curve> curve_index=7 v000=0 v127=1
+<effect> type=fverb bus=fx1 reverb_size=50
+";
+
+    /// The regions without where they are in the text, which writing changes.
+    fn unplaced(regions: &[Region]) -> Vec<Region> {
+        regions.iter().map(|region| Region { span: 0..0, ..region.clone() }).collect()
+    }
+
+    #[test]
+    fn written_instruments_load_back_the_same() {
+        for style in [WriteStyle::OnePerLine, WriteStyle::Compact] {
+            let instrument = parse_sfz(INSTRUMENT).unwrap();
+            let written = to_sfz_string(&instrument, style);
+            let reloaded = parse_sfz(&written).unwrap();
+            assert_eq!(unplaced(&reloaded.region), unplaced(&instrument.region));
+            assert_eq!(to_sfz_string(&reloaded, style), written);
+        }
+    }
+
+    #[test]
+    fn writes_aliases_as_spelled() {
+        let written = to_sfz_string(&parse_sfz(INSTRUMENT).unwrap(), WriteStyle::Compact);
+        assert!(written.contains("loopmode=loop_continuous"));
+        assert!(written.contains("cutoff_cc74=1200"));
+    }
+
+    #[test]
+    fn writes_included_headers_once() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("a.sfz", "<control>\n#include \"b.sfz\"\n<region> sample=a.wav\n");
+        loader.insert("b.sfz", "<global> pan=10\n<region> sample=inc.wav\n");
+        let (instrument, _) = load_sfz(&Preprocessor::new(loader), "a.sfz").unwrap();
+        let written = to_sfz_string(&instrument, WriteStyle::Compact);
+        assert!(!written.contains("#include"));
+
+        let mut loader = MemoryLoader::new();
+        loader.insert("a.sfz", written);
+        let (reloaded, _) = load_sfz(&Preprocessor::new(loader), "a.sfz").unwrap();
+        assert_eq!(unplaced(&reloaded.region), unplaced(&instrument.region));
+        assert_eq!(reloaded.global.len(), 1);
+    }
+
+    #[test]
+    fn writes_control_labels_in_number_order() {
+        let sfz_source = "<control> label_cc74=Cutoff label_key60=Middle C label_cc7=Volume <region> sample=a.wav";
+        let instrument = parse_sfz(sfz_source).unwrap();
+        let written = to_sfz_string(&instrument, WriteStyle::Compact);
+        assert!(written.starts_with("<control> label_cc7=Volume label_cc74=Cutoff label_key60=Middle C\n"));
+        let reloaded = parse_sfz(&written).unwrap();
+        assert_eq!(reloaded.control[0].label_ccn, instrument.control[0].label_ccn);
+        assert_eq!(reloaded.control[0].label_keyn, instrument.control[0].label_keyn);
+    }
+}